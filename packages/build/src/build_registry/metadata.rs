@@ -17,12 +17,16 @@ pub fn build_metadata() -> cu::Result<()> {
     let mut out = String::new();
     let _ = writeln!(out, "#![allow(unused)]");
 
+    let mut digests = Vec::new();
+    collect_digests(&table, &mut digests);
     let mut path = PathStack::new();
     for (meta_pkg, value) in table {
         path.push(&meta_pkg);
         build_metadata_item(&mut out, 0, &meta_pkg, &mut path, "", &value)?;
         path.pop();
     }
+    // all digests regardless of cfg, for checking if a download is still used
+    let _ = writeln!(out, "pub static ALL_DIGEST: &[&str] = &{digests:?};");
 
     util::write_str_if_modified("registry metadata", &metadata_output_path, &out)?;
     Ok(())
@@ -71,10 +75,92 @@ fn build_metadata_module(
         }
         path.pop();
     }
+    build_integrity_fns(out, depth + 1, table);
     let _ = writeln!(out, "{:width$}}}", "", width = depth * 4);
     Ok(())
 }
 
+/// Keys used to verify a download, the first digest found is the primary one
+const INTEGRITY_KEYS: &[&str] = &["SHA", "SHA512", "BLAKE3", "MINISIGN_KEY", "GPG_KEY"];
+
+/// Generate `pub fn integrity(url: &str) -> Integrity` for the download of the module,
+/// from the integrity keys of the module. One function is generated for each cfg
+/// with integrity keys, with the keys without cfg included
+fn build_integrity_fns(out: &mut String, depth: usize, table: &toml::Table) {
+    let integrity_keys = |table: &toml::Table| {
+        INTEGRITY_KEYS
+            .iter()
+            .copied()
+            .filter(|x| matches!(table.get(*x), Some(toml::Value::String(_))))
+            .collect::<Vec<_>>()
+    };
+    let base_keys = integrity_keys(table);
+    let mut cfgs = vec![];
+    for (key, value) in table {
+        let toml::Value::Table(value) = value else {
+            continue;
+        };
+        let Some(predicate) = key.strip_prefix("cfg(").and_then(|x| x.strip_suffix(')')) else {
+            continue;
+        };
+        let keys = integrity_keys(value);
+        if !keys.is_empty() {
+            cfgs.push((predicate, keys));
+        }
+    }
+    if cfgs.is_empty() {
+        build_integrity_fn(out, depth, "", &base_keys);
+        return;
+    }
+    for (predicate, keys) in &cfgs {
+        let keys = INTEGRITY_KEYS
+            .iter()
+            .copied()
+            .filter(|x| base_keys.contains(x) || keys.contains(x))
+            .collect::<Vec<_>>();
+        build_integrity_fn(out, depth, &format!("#[cfg({predicate})]"), &keys);
+    }
+    let predicates = cfgs.iter().map(|(x, _)| *x).collect::<Vec<_>>().join(", ");
+    build_integrity_fn(
+        out,
+        depth,
+        &format!("#[cfg(not(any({predicates})))]"),
+        &base_keys,
+    );
+}
+
+fn build_integrity_fn(out: &mut String, depth: usize, cfg_attr: &str, keys: &[&str]) {
+    let Some(primary) = keys
+        .iter()
+        .find(|x| matches!(**x, "SHA" | "SHA512" | "BLAKE3"))
+    else {
+        // a digest is required to identify the download
+        return;
+    };
+    let constructor = match *primary {
+        "SHA" => "sha256",
+        "SHA512" => "sha512",
+        _ => "blake3",
+    };
+    let mut expr = format!("corelib::hmgr::Integrity::{constructor}({primary})");
+    for key in keys {
+        match *key {
+            x if x == *primary => {}
+            "SHA512" => expr.push_str(".and_sha512(SHA512)"),
+            "BLAKE3" => expr.push_str(".and_blake3(BLAKE3)"),
+            "MINISIGN_KEY" => expr.push_str(".minisign(format!(\"{url}.minisig\"), MINISIGN_KEY)"),
+            "GPG_KEY" => expr.push_str(".gpg(format!(\"{url}.sig\"), GPG_KEY)"),
+            _ => {}
+        }
+    }
+    let indent = depth * 4;
+    let _ = writeln!(
+        out,
+        "{:indent$}{cfg_attr}pub fn integrity(url: &str) -> corelib::hmgr::Integrity {{ {expr} }}",
+        ""
+    );
+}
+
 fn build_metadata_item(
     out: &mut String,
     depth: usize,
@@ -151,6 +237,19 @@ fn build_metadata_item(
     Ok(())
 }
 
+/// Collect the values of all digest keys (`SHA`, `SHA512` and `BLAKE3`) in the table
+fn collect_digests(table: &toml::Table, out: &mut Vec<String>) {
+    for (key, value) in table {
+        match value {
+            toml::Value::String(s) if matches!(key.as_str(), "SHA" | "SHA512" | "BLAKE3") => {
+                out.push(s.trim_matches(['\'', '"']).to_ascii_lowercase());
+            }
+            toml::Value::Table(table) => collect_digests(table, out),
+            _ => {}
+        }
+    }
+}
+
 struct PathStack(Vec<String>);
 
 impl PathStack {
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use corelib::{hmgr, opfs};
 use cu::pre::*;
 
@@ -23,6 +25,10 @@ pub struct CliApi {
     /// Same as the version subcommand, -v to run self-check
     #[clap(short = 'V', long)]
     version: bool,
+
+    /// Don't reach the network, only use cached downloads. Can also be set in core.toml
+    #[clap(long, global = true)]
+    offline: bool,
 }
 impl CliApi {
     pub fn preprocess(&mut self) {
@@ -30,13 +36,14 @@ impl CliApi {
             self.flags.merge(command.as_ref());
         }
     }
-    pub fn run(self) -> cu::Result<()> {
+    /// Run the command, and return the exit code of the process
+    pub fn run(self) -> cu::Result<i32> {
         let run_version = self.version || matches!(&self.command, Some(CliCommand::Version(_)));
         if run_version {
             cu::lv::disable_print_time();
             println!("{}", env!("CARGO_PKG_VERSION"));
             if !cu::lv::D.enabled() {
-                return Ok(());
+                return Ok(0);
             }
         }
 
@@ -59,6 +66,9 @@ impl CliApi {
         )?;
         cu::check!(crate::init::check_init_home(), "failed to init home")?;
         let config = crate::config::load_config()?;
+        hmgr::set_offline(self.offline || config.network.offline);
+        hmgr::init_network(config.network.clone());
+        hmgr::init_link(config.link.clone());
         cu::check!(
             crate::init::check_init_environment(&config),
             "failed to init environment"
@@ -66,16 +76,20 @@ impl CliApi {
 
         if run_version {
             cu::info!("self-check OK");
-            return Ok(());
+            return Ok(0);
         }
 
         let Some(command) = self.command else {
             cu::lv::disable_print_time();
             cu::cli::print_help::<Self>(false);
-            return Ok(());
+            return Ok(0);
         };
 
-        let _lock = hmgr::lock()?;
+        // doctor is read-only, and checks the lock itself
+        let _lock = match &command {
+            CliCommand::Doctor(_) => None,
+            _ => Some(hmgr::lock()?),
+        };
 
         command.run()
     }
@@ -89,6 +103,38 @@ pub enum CliCommand {
     Sync(CliCommandSync),
     /// Remove package(s)
     Remove(CliCommandRemove),
+    /// Remove packages installed as dependencies that are no longer needed
+    Autoremove(CliCommandAutoremove),
+    /// Download package(s) without installing, for syncing offline later
+    Fetch(CliCommandFetch),
+    /// Create or install an offline installer bundle
+    Bundle(CliCommandBundle),
+    /// Install packages listed in a manifest file
+    Apply(CliCommandApply),
+    /// Change the package that provides a binary
+    Swap(CliCommandSwap),
+    /// Roll back a package to the previous installation
+    Rollback(CliCommandRollback),
+    /// Save or restore the state of the tool and installed packages
+    Snapshot(CliCommandSnapshot),
+    /// Manage the download cache
+    Cache(CliCommandCache),
+    /// Hold package(s) at the installed version, so sync doesn't upgrade them
+    Hold(CliCommandHold),
+    /// Allow held package(s) to be upgraded again
+    Unhold(CliCommandUnhold),
+    /// Verify installed packages without syncing.
+    ///
+    /// Exits with 1 if verification fails, or 2 if some packages are not up-to-date
+    Status(CliCommandStatus),
+    /// Check the health of the environment
+    Doctor(CliCommandDoctor),
+    /// Show why a package is installed
+    Why(CliCommandWhy),
+    /// Export the dependency graph
+    Graph(CliCommandGraph),
+    /// List or show past runs of commands that changed the installation
+    History(CliCommandHistory),
     /// Edit configuration for a package
     Config(CliCommandConfig),
     /// Search or print info of a package or binary
@@ -104,6 +150,21 @@ impl AsRef<cu::cli::Flags> for CliCommand {
             CliCommand::Upgrade(x) => x.as_ref(),
             CliCommand::Sync(x) => x.as_ref(),
             CliCommand::Remove(x) => x.as_ref(),
+            CliCommand::Autoremove(x) => x.as_ref(),
+            CliCommand::Fetch(x) => x.as_ref(),
+            CliCommand::Bundle(x) => x.as_ref(),
+            CliCommand::Apply(x) => x.as_ref(),
+            CliCommand::Swap(x) => x.as_ref(),
+            CliCommand::Rollback(x) => x.as_ref(),
+            CliCommand::Snapshot(x) => x.as_ref(),
+            CliCommand::Cache(x) => x.as_ref(),
+            CliCommand::Hold(x) => x.as_ref(),
+            CliCommand::Unhold(x) => x.as_ref(),
+            CliCommand::Status(x) => x.as_ref(),
+            CliCommand::Doctor(x) => x.as_ref(),
+            CliCommand::Why(x) => x.as_ref(),
+            CliCommand::Graph(x) => x.as_ref(),
+            CliCommand::History(x) => x.as_ref(),
             CliCommand::Config(x) => x.as_ref(),
             CliCommand::Clean(x) => x.as_ref(),
             CliCommand::Info(x) => x.as_ref(),
@@ -112,17 +173,33 @@ impl AsRef<cu::cli::Flags> for CliCommand {
     }
 }
 impl CliCommand {
-    pub fn run(self) -> cu::Result<()> {
+    /// Run the command, and return the exit code of the process
+    pub fn run(self) -> cu::Result<i32> {
         match self {
             CliCommand::Version(_) => {}
             CliCommand::Upgrade(cmd) => cmd.run()?,
             CliCommand::Sync(cmd) => cmd.run()?,
             CliCommand::Remove(cmd) => cmd.run()?,
+            CliCommand::Autoremove(cmd) => cmd.run()?,
+            CliCommand::Fetch(cmd) => cmd.run()?,
+            CliCommand::Bundle(cmd) => cmd.run()?,
+            CliCommand::Apply(cmd) => cmd.run()?,
+            CliCommand::Swap(cmd) => cmd.run()?,
+            CliCommand::Rollback(cmd) => cmd.run()?,
+            CliCommand::Snapshot(cmd) => cmd.run()?,
+            CliCommand::Cache(cmd) => cmd.run()?,
+            CliCommand::Hold(cmd) => cmd.run()?,
+            CliCommand::Unhold(cmd) => cmd.run()?,
+            CliCommand::Status(cmd) => return Ok(cmd.run()?.exit_code()),
+            CliCommand::Doctor(cmd) => cmd.run()?,
+            CliCommand::Why(cmd) => cmd.run()?,
+            CliCommand::Graph(cmd) => cmd.run()?,
+            CliCommand::History(cmd) => cmd.run()?,
             CliCommand::Config(cmd) => cmd.run()?,
             CliCommand::Info(cmd) => cmd.run()?,
             CliCommand::Clean(cmd) => cmd.run()?,
         }
-        Ok(())
+        Ok(0)
     }
 }
 
@@ -135,7 +212,7 @@ pub struct CliCommandUpgrade {
 
 impl CliCommandUpgrade {
     fn run(&self) -> cu::Result<()> {
-        corelib::hmgr::repo::local_update()
+        crate::history::record(corelib::hmgr::repo::local_update)
     }
 }
 
@@ -143,13 +220,49 @@ impl CliCommandUpgrade {
 pub struct CliCommandSync {
     /// Package(s) to install or update. If none specified, will update all installed packages.
     pub packages: Vec<String>,
+    /// Print the packages that would be synced and how, without changing anything
+    #[clap(long)]
+    pub plan: bool,
+    /// Same as --plan, but fail if anything would change
+    #[clap(long, conflicts_with = "plan")]
+    pub check: bool,
+    /// Select the provider for a binary when multiple packages provide it, e.g. --provider git=system-git
+    #[clap(long = "provider", value_name = "BIN=PKG")]
+    pub providers: Vec<String>,
+    /// Download and install the package(s) again, even if up-to-date
+    #[clap(long)]
+    pub reinstall: bool,
+    /// Configure the package(s) again, even if up-to-date
+    #[clap(long, conflicts_with = "reinstall")]
+    pub reconfigure: bool,
+    /// Sync all installed packages. Required to use --reinstall or --reconfigure without packages
+    #[clap(short, long, conflicts_with = "packages")]
+    pub all: bool,
     #[clap(flatten)]
     #[as_ref]
     pub flags: cu::cli::Flags,
 }
 impl CliCommandSync {
     fn run(&self) -> cu::Result<()> {
-        crate::cmds::sync(&self.packages)
+        let force = if self.reinstall {
+            Some(crate::cmds::ForceSync::Reinstall)
+        } else if self.reconfigure {
+            Some(crate::cmds::ForceSync::Reconfigure)
+        } else {
+            None
+        };
+        if self.plan || self.check {
+            return crate::cmds::sync_plan(
+                &self.packages,
+                &self.providers,
+                force,
+                self.all,
+                self.check,
+            );
+        }
+        crate::history::record(|| {
+            crate::cmds::sync(&self.packages, &self.providers, force, self.all)
+        })
     }
 }
 
@@ -160,13 +273,357 @@ pub struct CliCommandRemove {
     /// Force uninstall when package is in an unclean state.
     #[clap(short, long)]
     pub force: bool,
+    /// Also remove installed packages that depend on the package(s)
+    #[clap(long)]
+    pub cascade: bool,
+    /// Also delete the config, old installation, temporary files, version cache entries and downloads
+    #[clap(long)]
+    pub purge: bool,
+    /// Print the packages that would be removed or re-configured, without changing anything
+    #[clap(long)]
+    pub plan: bool,
+    /// Same as --plan, but fail if anything would change
+    #[clap(long, conflicts_with = "plan")]
+    pub check: bool,
+    /// Select the provider for a binary when multiple packages provide it, e.g. --provider git=system-git
+    #[clap(long = "provider", value_name = "BIN=PKG")]
+    pub providers: Vec<String>,
     #[clap(flatten)]
     #[as_ref]
     pub flags: cu::cli::Flags,
 }
 impl CliCommandRemove {
     fn run(&self) -> cu::Result<()> {
-        crate::cmds::remove(&self.packages, self.force)
+        if self.plan || self.check {
+            return crate::cmds::remove_plan(
+                &self.packages,
+                self.force,
+                self.cascade,
+                &self.providers,
+                self.check,
+            );
+        }
+        crate::history::record(|| {
+            crate::cmds::remove(
+                &self.packages,
+                self.force,
+                self.cascade,
+                self.purge,
+                &self.providers,
+            )
+        })
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandAutoremove {
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandAutoremove {
+    fn run(&self) -> cu::Result<()> {
+        crate::history::record(crate::cmds::autoremove)
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandFetch {
+    /// Package(s) to download, including their dependencies. If none specified, will download all installed packages.
+    pub packages: Vec<String>,
+    /// Select the provider for a binary when multiple packages provide it, e.g. --provider git=system-git
+    #[clap(long = "provider", value_name = "BIN=PKG")]
+    pub providers: Vec<String>,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandFetch {
+    fn run(&self) -> cu::Result<()> {
+        crate::cmds::fetch(&self.packages, &self.providers)
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandBundle {
+    #[clap(subcommand)]
+    pub command: CliCommandBundleSub,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+#[derive(clap::Subcommand, Debug)]
+pub enum CliCommandBundleSub {
+    /// Write an archive with the shaft binary, tools, downloads and config needed to install packages offline
+    Create {
+        /// Path to the archive to create (e.g. bundle.tar.gz)
+        output: PathBuf,
+        /// Package(s) to bundle, including their dependencies. If none specified, will bundle all explicitly installed packages.
+        packages: Vec<String>,
+        /// Select the provider for a binary when multiple packages provide it, e.g. --provider git=system-git
+        #[clap(long = "provider", value_name = "BIN=PKG")]
+        providers: Vec<String>,
+        /// Platform to bundle for. Only the current platform is supported
+        #[clap(long)]
+        target: Option<String>,
+    },
+    /// Install the packages in a bundle without network
+    Install {
+        /// Path to the archive created with `shaft bundle create`
+        archive: PathBuf,
+    },
+}
+impl CliCommandBundle {
+    fn run(&self) -> cu::Result<()> {
+        match &self.command {
+            CliCommandBundleSub::Create {
+                output,
+                packages,
+                providers,
+                target,
+            } => crate::cmds::bundle_create(output, packages, providers, target.as_deref()),
+            CliCommandBundleSub::Install { archive } => {
+                crate::history::record(|| crate::cmds::bundle_install(archive))
+            }
+        }
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandApply {
+    /// Path to the manifest file (e.g. shaft.toml)
+    pub manifest: PathBuf,
+    /// Remove installed packages that are not required by the manifest
+    #[clap(long)]
+    pub prune: bool,
+    /// Select the provider for a binary when multiple packages provide it, e.g. --provider git=system-git
+    #[clap(long = "provider", value_name = "BIN=PKG")]
+    pub providers: Vec<String>,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandApply {
+    fn run(&self) -> cu::Result<()> {
+        crate::history::record(|| crate::cmds::apply(&self.manifest, self.prune, &self.providers))
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandSwap {
+    /// The binary to change provider for
+    pub binary: String,
+    /// The package to provide the binary instead
+    pub provider: String,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandSwap {
+    fn run(&self) -> cu::Result<()> {
+        crate::history::record(|| crate::cmds::swap(&self.binary, &self.provider))
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandRollback {
+    /// The package to roll back
+    pub package: String,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandRollback {
+    fn run(&self) -> cu::Result<()> {
+        crate::history::record(|| crate::cmds::rollback(&self.package))
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandSnapshot {
+    #[clap(subcommand)]
+    pub command: CliCommandSnapshotSub,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+#[derive(clap::Subcommand, Debug)]
+pub enum CliCommandSnapshotSub {
+    /// Save the install cache, version cache, environment, items and config to a new snapshot
+    Create {
+        /// Also save the installed packages, which can be large
+        #[clap(long)]
+        install: bool,
+    },
+    /// Restore a snapshot, and rebuild the items
+    Restore {
+        /// Id of the snapshot, as shown in the list
+        id: u64,
+    },
+    /// List the snapshots
+    List,
+    /// Remove old snapshots
+    Prune {
+        /// Number of latest snapshots to keep
+        #[clap(short = 'n', long, default_value_t = 5)]
+        keep: usize,
+    },
+}
+impl CliCommandSnapshot {
+    fn run(&self) -> cu::Result<()> {
+        match &self.command {
+            CliCommandSnapshotSub::Create { install } => crate::cmds::snapshot_create(*install),
+            CliCommandSnapshotSub::Restore { id } => {
+                crate::history::record(|| crate::cmds::snapshot_restore(*id))
+            }
+            CliCommandSnapshotSub::List => crate::cmds::snapshot_list(),
+            CliCommandSnapshotSub::Prune { keep } => crate::cmds::snapshot_prune(*keep),
+        }
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandCache {
+    #[clap(subcommand)]
+    pub command: CliCommandCacheSub,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+#[derive(clap::Subcommand, Debug)]
+pub enum CliCommandCacheSub {
+    /// List the files in the download cache, with the packages that use them
+    List,
+    /// Remove downloads not needed by the installed packages, and leftover temporary files
+    Gc {
+        /// Also remove the least recently used downloads until the cache is under this size (in MiB)
+        #[clap(long)]
+        max_size: Option<u64>,
+    },
+    /// Re-hash the files in the download cache, and remove the corrupted ones
+    Verify,
+}
+impl CliCommandCache {
+    fn run(&self) -> cu::Result<()> {
+        match &self.command {
+            CliCommandCacheSub::List => crate::cmds::cache_list(),
+            CliCommandCacheSub::Gc { max_size } => crate::cmds::cache_gc(*max_size),
+            CliCommandCacheSub::Verify => crate::cmds::cache_verify(),
+        }
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandHold {
+    /// Package(s) to hold
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandHold {
+    fn run(&self) -> cu::Result<()> {
+        crate::cmds::hold(&self.packages)
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandUnhold {
+    /// Package(s) to unhold
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandUnhold {
+    fn run(&self) -> cu::Result<()> {
+        crate::cmds::unhold(&self.packages)
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandStatus {
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandStatus {
+    fn run(&self) -> cu::Result<crate::cmds::StatusResult> {
+        crate::cmds::status()
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandDoctor {
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandDoctor {
+    fn run(&self) -> cu::Result<()> {
+        crate::cmds::doctor()
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandWhy {
+    /// The package to show dependents for
+    pub package: String,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandWhy {
+    fn run(&self) -> cu::Result<()> {
+        crate::cmds::why(&self.package)
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandGraph {
+    /// Output format
+    #[clap(short, long, default_value = "dot")]
+    pub format: crate::cmds::GraphFormat,
+    /// Include all packages in the registry, instead of only installed packages
+    #[clap(short, long)]
+    pub all: bool,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+impl CliCommandGraph {
+    fn run(&self) -> cu::Result<()> {
+        crate::cmds::graph_export(self.format, self.all)
+    }
+}
+
+#[derive(clap::Parser, Debug, AsRef)]
+pub struct CliCommandHistory {
+    #[clap(subcommand)]
+    pub command: Option<CliCommandHistorySub>,
+    /// Number of records to list
+    #[clap(short = 'n', long, default_value_t = 20)]
+    pub count: usize,
+    #[clap(flatten)]
+    #[as_ref]
+    pub flags: cu::cli::Flags,
+}
+#[derive(clap::Subcommand, Debug)]
+pub enum CliCommandHistorySub {
+    /// Print the details of a record
+    Show {
+        /// Id of the record, as shown in the list
+        id: u64,
+    },
+}
+impl CliCommandHistory {
+    fn run(&self) -> cu::Result<()> {
+        match &self.command {
+            None => crate::cmds::history_list(self.count),
+            Some(CliCommandHistorySub::Show { id }) => crate::cmds::history_show(*id),
+        }
     }
 }
 
@@ -277,6 +734,6 @@ pub struct CliCommandClean {
 
 impl CliCommandClean {
     fn run(self) -> cu::Result<()> {
-        crate::cmds::clean(&self.package, self.all)
+        crate::history::record(|| crate::cmds::clean(&self.package, self.all))
     }
 }
@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use corelib::hmgr;
+use cu::pre::*;
+use enumset::EnumSet;
+use itertools::Itertools as _;
+use registry::PkgId;
+
+use crate::cmds::{RemoveOptions, SyncOptions};
+use crate::graph::{self, InstallCache};
+use crate::manifest::Manifest;
+
+/// Converge the installed packages to the manifest
+pub fn apply(manifest_path: &Path, prune: bool, providers: &[String]) -> cu::Result<()> {
+    let mut manifest = Manifest::load(manifest_path)?;
+    // providers from the command line take precedence
+    for (bin_id, pkg_id) in graph::parse_providers(providers)? {
+        if pkg_id.is_some() {
+            manifest.providers[bin_id] = pkg_id;
+        }
+    }
+    let mut installed = InstallCache::load()?;
+    for (pkg, overrides) in &manifest.config_overrides {
+        if overrides.is_some() && !manifest.pkgs.contains(pkg) && !installed.pkgs.contains(pkg) {
+            cu::bail!(
+                "config is given for '{pkg}', but it is not installed or listed in the packages of the manifest"
+            );
+        }
+    }
+
+    // packages that should be installed, including dependencies
+    let mut provider_selection = manifest.providers.clone();
+    let mut wanted = EnumSet::new();
+    wanted.insert(PkgId::Core);
+    for pkg in manifest.pkgs {
+        cu::check!(
+            graph::collect_dependencies(pkg, &installed, &mut wanted, &mut provider_selection),
+            "failed to collect dependencies for {pkg}"
+        )?;
+    }
+
+    let unlisted = installed.pkgs.difference(wanted);
+    if !unlisted.is_empty() {
+        let pkgs_string = unlisted.iter().map(|x| x.to_str()).join(", ");
+        if !prune {
+            cu::warn!(
+                "the following installed packages are not in the manifest: [ {pkgs_string} ]"
+            );
+            cu::hint!("use --prune to remove them");
+        } else {
+            cu::info!("the following packages will be removed: [ {pkgs_string} ]");
+            if !cu::yesno!("continue?")? {
+                cu::bail!("cancelled");
+            }
+            let options = RemoveOptions {
+                providers: manifest.providers,
+                ..Default::default()
+            };
+            cu::check!(
+                super::remove_pkgs(unlisted, &mut installed, &options),
+                "failed to remove packages not in the manifest"
+            )?;
+        }
+    }
+
+    // re-configure installed packages whose config doesn't have the overrides yet
+    let mut sync_pkgs = manifest.pkgs;
+    for (pkg, overrides) in &manifest.config_overrides {
+        let Some(overrides) = overrides else {
+            continue;
+        };
+        if !installed.pkgs.contains(pkg) {
+            continue;
+        }
+        if !installed.is_dirty(pkg) && !is_config_applied(pkg, overrides) {
+            cu::debug!("config overrides changed for '{pkg}'");
+            installed.set_dirty(pkg, true);
+        }
+        // installed packages not in the manifest are synced to apply the config
+        if installed.is_dirty(pkg) {
+            sync_pkgs.insert(pkg);
+        }
+    }
+    installed.save()?;
+
+    let options = SyncOptions {
+        providers: manifest.providers,
+        config_overrides: manifest.config_overrides,
+        explicit: manifest.pkgs,
+        ..Default::default()
+    };
+    super::sync_pkgs(sync_pkgs, &mut installed, &options)
+}
+
+fn is_config_applied(pkg: PkgId, overrides: &toml::Table) -> bool {
+    let path = hmgr::paths::config_file(pkg.to_str());
+    let Ok(content) = cu::fs::read_string(&path) else {
+        return false;
+    };
+    let Ok(mut value) = toml::parse::<toml::Table>(&content) else {
+        return false;
+    };
+    !hmgr::config::merge_config_values(&mut value, overrides)
+}
@@ -0,0 +1,37 @@
+use cu::pre::*;
+use enum_map::EnumMap;
+use enumset::EnumSet;
+use itertools::Itertools as _;
+use registry::PkgId;
+
+use crate::cmds::RemoveOptions;
+use crate::graph::{self, InstallCache};
+
+/// Remove packages installed as dependencies that are no longer needed
+/// by any explicitly installed package
+pub fn autoremove() -> cu::Result<()> {
+    let mut installed = InstallCache::load()?;
+    let mut provider_selection = EnumMap::default();
+    let mut needed = EnumSet::new();
+    for pkg in installed.explicit_pkgs() | PkgId::Core {
+        cu::check!(
+            graph::collect_dependencies(pkg, &installed, &mut needed, &mut provider_selection),
+            "failed to collect dependencies for {pkg}"
+        )?;
+    }
+    let unneeded = installed.pkgs.difference(needed);
+    if unneeded.is_empty() {
+        cu::info!("no unneeded packages to remove");
+        return Ok(());
+    }
+    let pkgs_string = unneeded.iter().map(|x| x.to_str()).join(", ");
+    cu::info!("the following packages are no longer needed and will be removed: [ {pkgs_string} ]");
+    if !cu::yesno!("continue?")? {
+        cu::bail!("cancelled");
+    }
+    let options = RemoveOptions {
+        providers: provider_selection,
+        ..Default::default()
+    };
+    super::remove_pkgs(unneeded, &mut installed, &options)
+}
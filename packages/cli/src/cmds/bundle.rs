@@ -0,0 +1,275 @@
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+use corelib::{ItemMgr, bin_name, hmgr, opfs};
+use cu::pre::*;
+use enumset::EnumSet;
+use itertools::Itertools as _;
+use registry::{Context, PkgId};
+
+use crate::cmds::SyncOptions;
+use crate::graph::{self, InstallCache};
+
+/// Latest version of the bundle format
+const BUNDLE_VERSION: u32 = 1;
+
+/// Metadata of an offline installer bundle, stored as `bundle.json` in the archive.
+///
+/// The archive also contains the `shaft` binary in `bin/`, the download artifacts
+/// in `download/` and the config files in `config/`. The tools are embedded in the binary
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct BundleJson {
+    /// Version of the bundle format
+    version: u32,
+    /// Version of the shaft binary in the bundle
+    cli_version: String,
+    /// The platform the bundle is created for, see [`current_target`]
+    target: String,
+    /// Packages to install, dependencies are resolved when installing
+    packages: Vec<String>,
+    /// Providers selected for binaries, in BIN=PKG format
+    providers: Vec<String>,
+    /// File names in the download directory
+    downloads: Vec<String>,
+}
+
+/// Create a bundle with everything needed to install the packages without network.
+/// If none specified, bundles the explicitly installed packages.
+///
+/// Only the current platform is supported as the target, since the downloads are
+/// resolved by the registry compiled for the platform
+pub fn bundle_create(
+    output: &Path,
+    packages: &[String],
+    providers: &[String],
+    target: Option<&str>,
+) -> cu::Result<()> {
+    let current = current_target();
+    if let Some(target) = target.filter(|x| *x != current) {
+        cu::hint!("run `shaft bundle create` on a '{target}' machine to bundle for it");
+        cu::bail!(
+            "cannot create bundle for '{target}', only the current platform '{current}' is supported"
+        );
+    }
+    let mut installed = InstallCache::load()?;
+    let pkgs = graph::parse_pkgs(packages)?;
+    let pkgs = if pkgs.is_empty() {
+        installed.explicit_pkgs()
+    } else {
+        pkgs
+    };
+    let mut provider_selection = graph::parse_providers(providers)?;
+    let levels = graph::build_sync_levels(pkgs, &installed, &mut provider_selection)?;
+    let all_pkgs: EnumSet<PkgId> = levels.into_iter().fold(pkgs, |acc, x| acc | x);
+    if all_pkgs.is_empty() {
+        cu::bail!("nothing to bundle");
+    }
+    cu::info!("bundling {} packages...", all_pkgs.len());
+
+    let items = ItemMgr::load()?;
+    let mut ctx = Context::new(items);
+    for pkg in installed.pkgs {
+        ctx.set_installed(pkg, true);
+    }
+
+    // packages installed with other package managers have nothing to bundle
+    let mut not_bundled = EnumSet::new();
+    for pkg in all_pkgs {
+        ctx.pkg = pkg;
+        for external in pkg.package().verify_report(&ctx).external {
+            cu::error!("'{pkg}' is installed with {external}, which cannot be bundled");
+            not_bundled.insert(pkg);
+        }
+    }
+    if !not_bundled.is_empty() {
+        let pkgs_string = not_bundled.iter().map(|x| x.to_str()).join(", ");
+        cu::hint!(
+            "select another provider with --provider, or install them on the target machine first"
+        );
+        cu::bail!("the following packages cannot be bundled: [ {pkgs_string} ]");
+    }
+
+    // download with empty resources, to know exactly which files are needed
+    let mut fetched = installed.clone();
+    for pkg in all_pkgs {
+        fetched.resources[pkg] = Default::default();
+    }
+    let result = super::sync::download_pkgs(&ctx, all_pkgs, &mut fetched);
+    for pkg in all_pkgs {
+        installed.resources[pkg].extend(fetched.resources[pkg].clone());
+    }
+    installed.save()?;
+    result?;
+    let downloads: BTreeSet<String> = all_pkgs
+        .iter()
+        .flat_map(|pkg| fetched.resources[pkg].downloads.clone())
+        .collect();
+
+    let bundle = BundleJson {
+        version: BUNDLE_VERSION,
+        cli_version: opfs::cli_version().to_string(),
+        target: current,
+        packages: pkgs.iter().map(|x| x.to_string()).collect(),
+        providers: provider_selection
+            .iter()
+            .filter(|(bin_id, _)| bin_id.providers().len() > 1)
+            .filter_map(|(bin_id, pkg_id)| Some(format!("{bin_id}={}", (*pkg_id)?)))
+            .collect(),
+        downloads: downloads.iter().cloned().collect(),
+    };
+
+    let staging = hmgr::paths::temp_dir("bundle");
+    cu::fs::make_dir_empty(&staging)?;
+    let bundle_json = staging.join("bundle.json");
+    cu::fs::write_json_pretty(&bundle_json, &bundle)?;
+
+    let mut files: Vec<(String, PathBuf)> = vec![
+        ("bundle.json".to_string(), bundle_json),
+        (
+            format!("bin/{}", bin_name!("shaft")),
+            std::env::current_exe()?,
+        ),
+    ];
+    for file in downloads {
+        let path = hmgr::paths::download_root().join(&file);
+        files.push((format!("download/{file}"), path));
+    }
+    for entry in cu::fs::read_dir(hmgr::paths::config_root())? {
+        let entry = entry?;
+        let name = entry.file_name().into_utf8()?;
+        if name.ends_with(".toml") {
+            files.push((format!("config/{name}"), entry.path()));
+        }
+    }
+
+    let bar = cu::progress("writing bundle").spawn();
+    let result = opfs::targz_files(output, &files);
+    bar.done();
+    hmgr::paths::clean_temp_dir("bundle");
+    result?;
+    cu::info!("bundle created at '{}'", output.display());
+    cu::hint!(
+        "on the new machine, extract the shaft binary from bin/ in the bundle, and run `shaft bundle install <bundle>`"
+    );
+    Ok(())
+}
+
+/// Install the packages in a bundle without network
+pub fn bundle_install(archive: &Path) -> cu::Result<()> {
+    let staging = hmgr::paths::temp_dir("bundle");
+    {
+        let bar = cu::progress("extracting bundle").spawn();
+        let reader = cu::fs::reader(archive)?;
+        let options = opfs::ExtractOptions {
+            clean: true,
+            file_mode: opfs::FileMode::Preserve,
+            ..Default::default()
+        };
+        opfs::untargz_read_with(reader, &staging, &options)?;
+        bar.done();
+    }
+    let result = install_from_staging(&staging);
+    hmgr::paths::clean_temp_dir("bundle");
+    let (pkgs, options) = result?;
+
+    // everything needed is in the download cache
+    hmgr::set_offline(true);
+    let mut installed = InstallCache::load()?;
+    super::sync_pkgs(pkgs, &mut installed, &options)
+}
+
+/// Move the files from the extracted bundle into HOME, and get what to sync
+fn install_from_staging(staging: &Path) -> cu::Result<(EnumSet<PkgId>, SyncOptions)> {
+    let content = cu::fs::read_string(staging.join("bundle.json"))?;
+    let bundle: BundleJson = cu::check!(json::parse(&content), "failed to parse bundle.json")?;
+    if bundle.version > BUNDLE_VERSION {
+        cu::bail!(
+            "bundle version {} is not supported, upgrade shaft to install it",
+            bundle.version
+        );
+    }
+    let target = current_target();
+    if bundle.target != target {
+        cu::bail!(
+            "bundle is created for '{}', but this machine is '{target}'",
+            bundle.target
+        );
+    }
+    if bundle.cli_version != opfs::cli_version() {
+        cu::hint!(
+            "extract the shaft binary from bin/ in the bundle, and use it to install the bundle"
+        );
+        cu::bail!(
+            "bundle is created with shaft {}, but this is shaft {}",
+            bundle.cli_version,
+            opfs::cli_version()
+        );
+    }
+    let pkgs = graph::parse_pkgs(&bundle.packages)?;
+    let options = SyncOptions {
+        providers: graph::parse_providers(&bundle.providers)?,
+        explicit: pkgs,
+        ..Default::default()
+    };
+
+    // the names are from the bundle, so they must not point outside of the download directory
+    let staging_download = staging.join("download");
+    for file in &bundle.downloads {
+        let mut components = Path::new(file).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            cu::bail!("invalid download file name in bundle.json: '{file}'");
+        }
+        if !staging_download.join(file).is_file() {
+            cu::bail!("download file '{file}' listed in bundle.json is missing from the bundle");
+        }
+    }
+    cu::fs::make_dir(hmgr::paths::download_root())?;
+    for file in &bundle.downloads {
+        let target_path = hmgr::paths::download_root().join(file);
+        if !target_path.exists() {
+            cu::fs::rename(staging_download.join(file), &target_path)?;
+        }
+    }
+    cu::fs::make_dir(hmgr::paths::config_root())?;
+    let config_dir = staging.join("config");
+    if config_dir.exists() {
+        for entry in cu::fs::read_dir(&config_dir)? {
+            let entry = entry?;
+            let target_path = hmgr::paths::config_root().join(entry.file_name());
+            if target_path.exists() {
+                cu::warn!("keeping existing config '{}'", target_path.display());
+                continue;
+            }
+            cu::fs::rename(entry.path(), &target_path)?;
+        }
+    }
+    let shaft_path = hmgr::paths::binary(bin_name!("shaft"));
+    if !shaft_path.exists() {
+        cu::fs::make_dir(hmgr::paths::bin_root())?;
+        cu::fs::rename(staging.join("bin").join(bin_name!("shaft")), &shaft_path)?;
+        opfs::set_executable(&shaft_path)?;
+        cu::info!("copied shaft to $SHAFT_HOME/bin");
+    }
+    // the tools are embedded in the binary, which is the same version
+    hmgr::tools::ensure_unpacked()?;
+
+    Ok((pkgs, options))
+}
+
+/// The platform packages are installed for, which determines the downloads
+fn current_target() -> String {
+    let os = std::env::consts::OS;
+    let arch = std::env::consts::ARCH;
+    #[cfg(target_os = "linux")]
+    {
+        format!("{arch}-{os}-{}", opfs::linux_flavor())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        format!("{arch}-{os}")
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::BTreeSet;
+
+use corelib::hmgr::{self, Digest, DownloadIndex};
+use corelib::opfs;
+use cu::pre::*;
+use registry::PkgId;
+
+use crate::graph::InstallCache;
+
+/// List the files in the download cache
+pub fn cache_list() -> cu::Result<()> {
+    let index = DownloadIndex::load()?;
+    let files = list_files()?;
+    if files.is_empty() {
+        cu::info!("download cache is empty");
+        return Ok(());
+    }
+    cu::lv::disable_print_time();
+    cu::hint!("{:>10} | {:<20} | file | url", "size", "package");
+    let mut total = 0;
+    for (file, size) in &files {
+        total += size;
+        let (package, url) = match index.entries.get(file) {
+            Some(entry) => {
+                let package = match (&entry.package, &entry.version) {
+                    (Some(package), Some(version)) => format!("{package} {version}"),
+                    (Some(package), None) => package.clone(),
+                    _ => "-".to_string(),
+                };
+                (package, entry.url.as_str())
+            }
+            None => ("-".to_string(), "(not indexed)"),
+        };
+        cu::print!(
+            "{:>10} | {package:<20} | {file} | {url}",
+            format_size(*size)
+        );
+    }
+    cu::info!("{} files, {} in total", files.len(), format_size(total));
+    Ok(())
+}
+
+/// Remove files in the download cache that are not needed by the installed packages,
+/// and the leftover temporary directories.
+///
+/// A file is kept if its digest is used by the current registry, and it is referenced
+/// by an installed package. Packages installed before the downloads are tracked
+/// have no references, so a file not referenced by any installed package is also kept
+/// if the package that downloaded it is installed or unknown.
+///
+/// With `max_size` (in MiB), the least recently used files are also removed
+/// until the cache is under the size
+pub fn cache_gc(max_size: Option<u64>) -> cu::Result<()> {
+    let installed = InstallCache::load()?;
+    let referenced: BTreeSet<String> = installed
+        .pkgs
+        .iter()
+        .flat_map(|pkg| installed.resources[pkg].downloads.iter().cloned())
+        .collect();
+    let mut index = DownloadIndex::load()?;
+    let mut removed_count = 0;
+    let mut removed_size = 0;
+    let mut kept = vec![];
+    let mut malformed = 0;
+    {
+        let bar = cu::progress("checking download cache").spawn();
+        for (file, size) in list_files()? {
+            let path = hmgr::paths::download_root().join(&file);
+            let hex = match index.entries.get(&file) {
+                Some(entry) => match Digest::parse(&entry.digest) {
+                    Ok(digest) => digest.hex,
+                    Err(e) => {
+                        cu::warn!("skipping '{file}', malformed index entry: {e}");
+                        malformed += 1;
+                        continue;
+                    }
+                },
+                None => opfs::file_sha256(&path, Some(bar.clone()))?,
+            };
+            // downloads of older versions are still in the resources
+            let keep = registry::is_current_digest(&hex)
+                && (referenced.contains(&file) || is_owner_installed(&index, &file, &installed));
+            if keep {
+                let timestamp = index
+                    .entries
+                    .get(&file)
+                    .map(|x| x.timestamp)
+                    .unwrap_or_default();
+                kept.push((timestamp, file, size));
+                continue;
+            }
+            cu::debug!("removing '{file}'");
+            cu::fs::remove(&path)?;
+            index.entries.remove(&file);
+            removed_count += 1;
+            removed_size += size;
+        }
+        bar.done();
+    }
+    if let Some(max_size) = max_size {
+        let max_size = max_size * 1048576;
+        let mut total: u64 = kept.iter().map(|(_, _, size)| size).sum();
+        // least recently used first
+        kept.sort();
+        for (_, file, size) in kept {
+            if total <= max_size {
+                break;
+            }
+            cu::debug!("removing '{file}' to reduce cache size");
+            cu::fs::remove(hmgr::paths::download_root().join(&file))?;
+            index.entries.remove(&file);
+            removed_count += 1;
+            removed_size += size;
+            total -= size;
+        }
+    }
+    // entries of files removed in other ways
+    index
+        .entries
+        .retain(|file, _| hmgr::paths::download_root().join(file).exists());
+    index.save()?;
+    cu::info!(
+        "removed {removed_count} files from the download cache, freed {}",
+        format_size(removed_size)
+    );
+    if malformed > 0 {
+        cu::hint!(
+            "{malformed} files with malformed index entries are kept, run `shaft cache verify` to check them"
+        );
+    }
+
+    // the home is locked, so nothing is using the temp directories
+    let temp_root = hmgr::paths::temp_root();
+    if temp_root.exists() {
+        let mut count = 0;
+        for entry in cu::fs::read_dir(&temp_root)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                cu::fs::rec_remove(&path)?;
+            } else {
+                cu::fs::remove(&path)?;
+            }
+            count += 1;
+        }
+        if count > 0 {
+            cu::info!("removed {count} leftover temporary directories");
+        }
+    }
+    Ok(())
+}
+
+/// Re-hash the files in the download cache, and remove the ones that don't match the index
+pub fn cache_verify() -> cu::Result<()> {
+    let mut index = DownloadIndex::load()?;
+    let files = list_files()?;
+    let bar = cu::progress("verifying download cache")
+        .total(files.len())
+        .spawn();
+    let mut corrupted = vec![];
+    let mut unindexed = 0;
+    for (file, _) in files {
+        cu::progress!(bar += 1, "{file}");
+        let Some(entry) = index.entries.get(&file) else {
+            unindexed += 1;
+            continue;
+        };
+        let path = hmgr::paths::download_root().join(&file);
+        // files with malformed entries cannot be verified, treat them as corrupted
+        let result =
+            Digest::parse(&entry.digest).and_then(|digest| digest.verify(&path, Some(bar.clone())));
+        if let Err(e) = result {
+            cu::warn!("{e}");
+            cu::fs::remove(&path)?;
+            corrupted.push(file);
+        }
+    }
+    bar.done();
+    for file in &corrupted {
+        index.entries.remove(file);
+    }
+    index
+        .entries
+        .retain(|file, _| hmgr::paths::download_root().join(file).exists());
+    index.save()?;
+    if unindexed > 0 {
+        cu::hint!("{unindexed} files are not in the index and are not verified");
+    }
+    if corrupted.is_empty() {
+        cu::info!("all indexed files are valid");
+    } else {
+        cu::warn!(
+            "removed {} corrupted files, they will be downloaded again when needed",
+            corrupted.len()
+        );
+    }
+    Ok(())
+}
+
+/// Check if the package that downloaded the file is installed, according to the index.
+/// Files downloaded before the package is recorded are assumed to be used
+fn is_owner_installed(index: &DownloadIndex, file: &str, installed: &InstallCache) -> bool {
+    let Some(package) = index.entries.get(file).and_then(|x| x.package.as_deref()) else {
+        return true;
+    };
+    PkgId::from_str(package).is_some_and(|x| installed.pkgs.contains(x))
+}
+
+/// Get the file names and sizes in the download cache, excluding the index
+fn list_files() -> cu::Result<Vec<(String, u64)>> {
+    let root = hmgr::paths::download_root();
+    if !root.exists() {
+        return Ok(vec![]);
+    }
+    let index_json = hmgr::paths::download_index_json();
+    let mut files = vec![];
+    for entry in cu::fs::read_dir(&root)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path == index_json || !path.is_file() {
+            continue;
+        }
+        let name = entry.file_name().into_utf8()?;
+        let size = entry.metadata().map(|x| x.len()).unwrap_or_default();
+        files.push((name, size));
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / 1048576.0)
+}
@@ -2,6 +2,7 @@ use corelib::ItemMgr;
 use registry::{Context, PkgId, Stage};
 
 use crate::graph::{self, InstallCache};
+use crate::history;
 
 pub fn clean(packages: &[String], all: bool) -> cu::Result<()> {
     let installed = InstallCache::load()?;
@@ -31,7 +32,8 @@ pub fn clean(packages: &[String], all: bool) -> cu::Result<()> {
             ctx.pkg = pkg;
             let bar = cu::progress(format!("clean '{pkg}'")).spawn();
             ctx.set_bar(Some(&bar));
-            ctx.stage.set(Stage::Clean);
+            history::set_action(pkg, "clean");
+            history::set_stage(&ctx, Stage::Clean);
             if let Err(e) = pkg.package().clean(&ctx) {
                 cu::warn!("failed to clean package {pkg}: {e:?}");
             }
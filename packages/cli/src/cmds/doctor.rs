@@ -0,0 +1,241 @@
+use std::path::Path;
+
+use corelib::{ItemMgr, hmgr};
+use cu::pre::*;
+
+/// Check the health of the whole environment, and print a fix for each problem found
+pub fn doctor() -> cu::Result<()> {
+    let mut problems = Problems::default();
+    check_path(&mut problems)?;
+    #[cfg(not(windows))]
+    check_shell_profile(&mut problems);
+    let items = match ItemMgr::load() {
+        Ok(items) => Some(items),
+        Err(e) => {
+            problems.add(
+                format!("failed to load items: {e}"),
+                "run `shaft config --dirty` and `shaft sync` to re-configure all packages",
+            );
+            None
+        }
+    };
+    if let Some(items) = &items {
+        check_links(items, &mut problems);
+    }
+    check_leftovers(&mut problems);
+    check_environment(items.as_ref(), &mut problems);
+
+    if problems.0.is_empty() {
+        cu::info!("no problems found");
+        return Ok(());
+    }
+    for (problem, hint) in &problems.0 {
+        cu::error!("{problem}");
+        cu::hint!("  fix: {hint}");
+    }
+    match problems.0.len() {
+        1 => cu::bail!("found 1 problem"),
+        x => cu::bail!("found {x} problems"),
+    }
+}
+
+#[derive(Default)]
+struct Problems(Vec<(String, String)>);
+impl Problems {
+    fn add(&mut self, problem: impl Into<String>, hint: impl Into<String>) {
+        self.0.push((problem.into(), hint.into()));
+    }
+}
+
+/// Check HOME/bin is the first in PATH
+fn check_path(problems: &mut Problems) -> cu::Result<()> {
+    cu::debug!("doctor: checking PATH");
+    let bin_root = hmgr::paths::bin_root().normalize()?;
+    let path = cu::env_var("PATH")?;
+    let mut paths = std::env::split_paths(&path).filter(|x| !x.as_os_str().is_empty());
+    let is_bin_root = |p: &Path| p.normalize().is_ok_and(|x| x == bin_root);
+    let Some(first) = paths.next() else {
+        problems.add(
+            "PATH is empty",
+            "restart the shell to load the shaft init script",
+        );
+        return Ok(());
+    };
+    if is_bin_root(&first) {
+        return Ok(());
+    }
+    let fix = if cfg!(windows) {
+        "move '%SHAFT_HOME%\\bin' to the top of the PATH environment variable, note that system PATH takes priority over user PATH"
+    } else {
+        "source the shaft init script at the end of the shell profile, after other scripts that modify PATH"
+    };
+    if paths.any(|p| is_bin_root(&p)) {
+        problems.add(
+            format!(
+                "'{}' is before the shaft bin directory in PATH",
+                first.display()
+            ),
+            fix,
+        );
+    } else {
+        problems.add(
+            format!(
+                "the shaft bin directory '{}' is not in PATH",
+                bin_root.display()
+            ),
+            fix,
+        );
+    }
+    Ok(())
+}
+
+/// Check the init scripts are sourced by the shell profile
+#[cfg(not(windows))]
+fn check_shell_profile(problems: &mut Problems) {
+    cu::debug!("doctor: checking shell profile");
+    let shell = cu::env_var("SHELL").unwrap_or_default();
+    let shell = Path::new(&shell)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default();
+    let (init_script, profiles): (_, &[&str]) = match shell {
+        "bash" => (
+            hmgr::paths::init_bash(),
+            &[".bashrc", ".bash_profile", ".bash_login", ".profile"],
+        ),
+        "zsh" => (
+            hmgr::paths::init_zsh(),
+            &[".zshrc", ".zprofile", ".zshenv", ".zlogin"],
+        ),
+        _ => {
+            cu::warn!("unsupported shell '{shell}', only bash and zsh are checked");
+            return;
+        }
+    };
+    let Some(home) = std::env::home_dir() else {
+        cu::warn!("failed to get user home, not checking shell profile");
+        return;
+    };
+    if !init_script.exists() {
+        problems.add(
+            format!("init script does not exist: '{}'", init_script.display()),
+            "run `shaft sync core` to rebuild the init scripts",
+        );
+    }
+    let script_name = init_script
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default();
+    let needle = format!("items/{script_name}");
+    for profile in profiles {
+        let Ok(content) = cu::fs::read_string(home.join(profile)) else {
+            continue;
+        };
+        if content.contains(&needle) {
+            cu::debug!("doctor: '{needle}' is sourced in '{profile}'");
+            return;
+        }
+    }
+    problems.add(
+        format!("'{needle}' is not sourced by any {shell} profile"),
+        format!("add `. {}` to ~/{}", init_script.display(), profiles[0]),
+    );
+}
+
+/// Check links and shims in the bin directory
+fn check_links(items: &ItemMgr, problems: &mut Problems) {
+    cu::debug!("doctor: checking links");
+    for link in items.check_links() {
+        problems.add(
+            format!("'{}': {}", link.path.display(), link.reason),
+            format!(
+                "delete '{}', then run `shaft config --dirty {}` and `shaft sync`",
+                link.path.display(),
+                link.package
+            ),
+        );
+    }
+}
+
+/// Check files left over by runs that did not exit cleanly
+fn check_leftovers(problems: &mut Problems) {
+    cu::debug!("doctor: checking leftovers");
+    let lock_path = hmgr::paths::dot_lock();
+    if hmgr::is_lock_stale() {
+        problems.add(
+            format!("stale lock file: '{}'", lock_path.display()),
+            format!(
+                "make sure no other shaft process is running, then delete '{}'",
+                lock_path.display()
+            ),
+        );
+    } else if lock_path.exists() {
+        cu::warn!("another shaft process is running");
+    }
+    let temp_root = hmgr::paths::temp_root();
+    if let Ok(false) = cu::fs::is_empty_dir(&temp_root) {
+        problems.add(
+            format!("leftover files in '{}'", temp_root.display()),
+            "run `shaft clean` to remove them",
+        );
+    }
+    // old installations are kept on purpose if they can be rolled back to
+    let install_old_root = hmgr::paths::install_old_root();
+    let Ok(entries) = cu::fs::read_dir(&install_old_root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_kept = if path.is_dir() {
+            path.with_extension("json").exists()
+        } else {
+            path.extension().is_some_and(|x| x == "json") && path.with_extension("").is_dir()
+        };
+        if !is_kept {
+            problems.add(
+                format!("leftover files in '{}'", install_old_root.display()),
+                "run `shaft clean` to remove them",
+            );
+            return;
+        }
+    }
+}
+
+/// Check environment variables are consistent with environment.json and the items
+fn check_environment(items: Option<&ItemMgr>, problems: &mut Problems) {
+    cu::debug!("doctor: checking environment");
+    let fix = if cfg!(windows) {
+        "restart (all) terminal processes to refresh the environment"
+    } else {
+        "restart the shell to refresh the environment"
+    };
+    let home = hmgr::paths::home();
+    match cu::env_var("SHAFT_HOME") {
+        Ok(value) if Path::new(&value).normalize().ok().as_deref() == Some(home) => {}
+        _ => problems.add(
+            format!("SHAFT_HOME is not set to '{}'", home.display()),
+            fix,
+        ),
+    }
+    let asserts = match hmgr::env_asserts() {
+        Ok(x) => x,
+        Err(e) => {
+            let path = hmgr::paths::environment_json();
+            problems.add(
+                format!("failed to load '{}': {e}", path.display()),
+                format!("delete '{}' and restart the shell", path.display()),
+            );
+            Default::default()
+        }
+    };
+    let env_vars = items.and_then(|x| x.env_vars().ok()).unwrap_or_default();
+    for (key, expected) in asserts.into_iter().chain(env_vars) {
+        let actual = cu::env_var(&key).unwrap_or_default();
+        if actual != expected {
+            problems.add(
+                format!("env var '{key}' is '{actual}', expected '{expected}'"),
+                fix,
+            );
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use corelib::{ItemMgr, hmgr};
+use cu::pre::*;
+use registry::Context;
+
+use crate::graph::{self, InstallCache};
+
+/// Download the packages and their dependencies to the download cache,
+/// without installing anything. If none specified, downloads all installed packages
+pub fn fetch(packages: &[String], providers: &[String]) -> cu::Result<()> {
+    if hmgr::is_offline() {
+        cu::bail!("cannot fetch in offline mode");
+    }
+    let mut installed = InstallCache::load()?;
+    let pkgs = graph::parse_pkgs(packages)?;
+    let pkgs = if pkgs.is_empty() {
+        installed.pkgs
+    } else {
+        pkgs
+    };
+    let mut provider_selection = graph::parse_providers(providers)?;
+    let levels = graph::build_sync_levels(pkgs, &installed, &mut provider_selection)?;
+    let pkgs = levels.into_iter().fold(pkgs, |acc, x| acc | x);
+    match pkgs.len() {
+        0 => cu::bail!("nothing to do"),
+        1 => cu::info!("downloading 1 package..."),
+        x => cu::info!("downloading {x} packages..."),
+    }
+
+    let items = ItemMgr::load()?;
+    let mut ctx = Context::new(items);
+    for pkg in installed.pkgs {
+        ctx.set_installed(pkg, true);
+    }
+    let result = super::sync::download_pkgs(&ctx, pkgs, &mut installed);
+    // keep track of what is downloaded even if some failed
+    installed.save()?;
+    result?;
+    cu::info!("downloaded {} packages", pkgs.len());
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use std::fmt::Write as _;
+
+use cu::pre::*;
+use enumset::EnumSet;
+use registry::{BinId, PkgId};
+
+use crate::graph::{self, InstallCache};
+
+/// Output format of the dependency graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// JSON
+    Json,
+}
+
+/// Print the package and binary dependency graph of installed packages,
+/// or all packages in the registry
+pub fn graph_export(format: GraphFormat, all: bool) -> cu::Result<()> {
+    let installed = InstallCache::load()?;
+    let pkgs = if all {
+        EnumSet::<PkgId>::all()
+            .iter()
+            .filter(|x| x.package().enabled())
+            .collect()
+    } else {
+        installed.pkgs
+    };
+    let mut bins = EnumSet::<BinId>::new();
+    for pkg in pkgs {
+        let package = pkg.package();
+        bins |= package.binaries();
+        bins |= package.binary_dependencies();
+    }
+
+    let output = match format {
+        GraphFormat::Dot => export_dot(pkgs, bins, &installed),
+        GraphFormat::Json => export_json(pkgs, bins, &installed)?,
+    };
+    cu::lv::disable_print_time();
+    println!("{output}");
+    Ok(())
+}
+
+fn export_dot(pkgs: EnumSet<PkgId>, bins: EnumSet<BinId>, installed: &InstallCache) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph shaft {{");
+    let _ = writeln!(out, "  rankdir=LR;");
+    for pkg in pkgs {
+        let style = if installed.pkgs.contains(pkg) {
+            ", style=bold"
+        } else {
+            ""
+        };
+        let _ = writeln!(out, "  \"pkg:{pkg}\" [label=\"{pkg}\", shape=box{style}];");
+    }
+    for bin in bins {
+        let _ = writeln!(out, "  \"bin:{bin}\" [label=\"{bin}\", shape=ellipse];");
+    }
+    for pkg in pkgs {
+        let package = pkg.package();
+        for bin in package.binaries() {
+            let _ = writeln!(
+                out,
+                "  \"pkg:{pkg}\" -> \"bin:{bin}\" [label=\"provides\"];"
+            );
+        }
+        for bin in package.binary_dependencies() {
+            let style = match graph::resolve_provider(bin, installed) {
+                Some(_) => "",
+                None => ", style=dashed",
+            };
+            let _ = writeln!(
+                out,
+                "  \"bin:{bin}\" -> \"pkg:{pkg}\" [label=\"binary\"{style}];"
+            );
+        }
+        for cfg in package.config_dependencies() {
+            if !pkgs.contains(cfg) {
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "  \"pkg:{cfg}\" -> \"pkg:{pkg}\" [label=\"config\", style=dotted];"
+            );
+        }
+    }
+    let _ = writeln!(out, "}}");
+    out
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct GraphJson {
+    packages: Vec<PackageJson>,
+    binaries: Vec<BinaryJson>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct PackageJson {
+    name: &'static str,
+    installed: bool,
+    binaries: Vec<&'static str>,
+    binary_dependencies: Vec<&'static str>,
+    config_dependencies: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct BinaryJson {
+    name: &'static str,
+    providers: Vec<&'static str>,
+    /// The installed or selected provider
+    provider: Option<&'static str>,
+}
+
+fn export_json(
+    pkgs: EnumSet<PkgId>,
+    bins: EnumSet<BinId>,
+    installed: &InstallCache,
+) -> cu::Result<String> {
+    let packages = pkgs
+        .iter()
+        .map(|pkg| {
+            let package = pkg.package();
+            PackageJson {
+                name: pkg.to_str(),
+                installed: installed.pkgs.contains(pkg),
+                binaries: package.binaries().iter().map(|x| x.to_str()).collect(),
+                binary_dependencies: package
+                    .binary_dependencies()
+                    .iter()
+                    .map(|x| x.to_str())
+                    .collect(),
+                config_dependencies: package
+                    .config_dependencies()
+                    .iter()
+                    .map(|x| x.to_str())
+                    .collect(),
+            }
+        })
+        .collect();
+    let binaries = bins
+        .iter()
+        .map(|bin| BinaryJson {
+            name: bin.to_str(),
+            providers: bin.providers().iter().map(|x| x.to_str()).collect(),
+            provider: graph::resolve_provider(bin, installed).map(|x| x.to_str()),
+        })
+        .collect();
+    json::stringify(&GraphJson { packages, binaries })
+}
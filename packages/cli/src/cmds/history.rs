@@ -0,0 +1,80 @@
+use corelib::opfs;
+use cu::pre::*;
+use itertools::Itertools as _;
+
+use crate::history;
+
+/// List the latest records in the history
+pub fn history_list(count: usize) -> cu::Result<()> {
+    let ids = history::list_ids()?;
+    if ids.is_empty() {
+        cu::info!("no history");
+        return Ok(());
+    }
+    cu::lv::disable_print_time();
+    cu::hint!("{:>5} | {:<23} | {:<7} | command", "id", "time", "result");
+    for id in ids.iter().rev().take(count) {
+        let record = match history::load(*id) {
+            Ok(x) => x,
+            Err(e) => {
+                cu::warn!("{e:?}");
+                continue;
+            }
+        };
+        let result = if record.success { "ok" } else { "failed" };
+        cu::print!(
+            "{:>5} | {:<23} | {:<7} | {}",
+            record.id,
+            opfs::format_utc(record.timestamp),
+            result,
+            record.command
+        );
+    }
+    if ids.len() > count {
+        cu::hint!(
+            "showing {count} of {} records, use -n to show more",
+            ids.len()
+        );
+    }
+    Ok(())
+}
+
+/// Print the details of a record in the history
+pub fn history_show(id: u64) -> cu::Result<()> {
+    let record = history::load(id)?;
+    cu::lv::disable_print_time();
+    cu::hint!("=== [history: {}] ============", record.id);
+    cu::print!("time:     {}", opfs::format_utc(record.timestamp));
+    cu::print!("command:  {}", record.command);
+    cu::print!("duration: {}", format_ms(record.duration_ms));
+    cu::print!("result:   {}", if record.success { "ok" } else { "failed" });
+    if !record.graph.is_empty() {
+        cu::print!("graph:    [ {} ]", record.graph.iter().join(", "));
+    }
+    if !record.packages.is_empty() {
+        cu::hint!("packages:");
+        for package in &record.packages {
+            let stages = package
+                .stages
+                .iter()
+                .map(|(stage, ms)| format!("{stage} {}", format_ms(*ms)))
+                .join(", ");
+            cu::print!("  {} ({}): {stages}", package.name, package.action);
+        }
+    }
+    if !record.errors.is_empty() {
+        cu::hint!("errors:");
+        for (i, error) in record.errors.iter().enumerate() {
+            cu::print!("  {i}: {error}");
+        }
+    }
+    Ok(())
+}
+
+fn format_ms(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{ms}ms")
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
@@ -0,0 +1,33 @@
+use cu::pre::*;
+
+use crate::graph::{self, InstallCache};
+
+/// Hold packages at the installed version, so sync doesn't upgrade them
+pub fn hold(packages: &[String]) -> cu::Result<()> {
+    set_held(packages, true)
+}
+
+/// Allow held packages to be upgraded by sync again
+pub fn unhold(packages: &[String]) -> cu::Result<()> {
+    set_held(packages, false)
+}
+
+fn set_held(packages: &[String], held: bool) -> cu::Result<()> {
+    let pkgs = graph::parse_pkgs(packages)?;
+    let mut installed = InstallCache::load()?;
+    for pkg in pkgs {
+        if !installed.pkgs.contains(pkg) {
+            cu::bail!("'{pkg}' is not installed");
+        }
+    }
+    for pkg in pkgs {
+        match (installed.is_held(pkg), held) {
+            (true, true) => cu::info!("already held: '{pkg}'"),
+            (false, false) => cu::info!("not held: '{pkg}'"),
+            (_, true) => cu::info!("held: '{pkg}'"),
+            (_, false) => cu::info!("unheld: '{pkg}'"),
+        }
+        installed.set_held(pkg, held);
+    }
+    installed.save()
+}
@@ -1,7 +1,37 @@
 mod sync;
-pub use sync::{sync, sync_pkgs};
+pub use sync::{ForceSync, SyncOptions, SyncType, sync, sync_pkgs};
 mod remove;
-pub use remove::remove;
+pub use remove::{RemoveOptions, remove, remove_pkgs};
+mod autoremove;
+pub use autoremove::autoremove;
+mod fetch;
+pub use fetch::fetch;
+mod bundle;
+pub use bundle::{bundle_create, bundle_install};
+mod apply;
+pub use apply::apply;
+mod swap;
+pub use swap::swap;
+mod rollback;
+pub use rollback::{RollbackRecord, rollback};
+mod snapshot;
+pub use snapshot::{snapshot_create, snapshot_list, snapshot_prune, snapshot_restore};
+mod cache;
+pub use cache::{cache_gc, cache_list, cache_verify};
+mod hold;
+pub use hold::{hold, unhold};
+mod status;
+pub use status::{StatusResult, status};
+mod doctor;
+pub use doctor::doctor;
+mod why;
+pub use why::why;
+mod graph_export;
+pub use graph_export::{GraphFormat, graph_export};
+mod history;
+pub use history::{history_list, history_show};
+mod plan;
+pub use plan::{remove_plan, sync_plan};
 mod config;
 pub use config::{config, config_dirty, config_dirty_all, config_location};
 mod info;
@@ -0,0 +1,223 @@
+use corelib::ItemMgr;
+use cu::pre::*;
+use enum_map::EnumMap;
+use enumset::EnumSet;
+use itertools::Itertools as _;
+use registry::{BinId, Context, PkgId, Stage, Verified};
+
+use crate::cmds::{ForceSync, SyncType};
+use crate::graph::{self, InstallCache};
+
+/// Print what `shaft sync` would do, without changing anything.
+///
+/// With `check`, error if anything would change
+pub fn sync_plan(
+    packages: &[String],
+    providers: &[String],
+    force: Option<ForceSync>,
+    all: bool,
+    check: bool,
+) -> cu::Result<()> {
+    // this copy of the install cache is never saved
+    let mut installed = InstallCache::load()?;
+    let (pkgs, options) =
+        super::sync::parse_sync_args(packages, providers, force, all, &installed)?;
+    let mut provider_selection = options.providers;
+    if pkgs.is_empty() {
+        cu::info!("nothing to sync");
+        return Ok(());
+    }
+    graph::disable_provider_prompt();
+    let items_ok = ItemMgr::load().is_ok();
+    let core_version_uptodate = super::sync::core_version_cache().is_uptodate()?;
+    if !items_ok || matches!(core_version_uptodate, Some(false)) {
+        cu::warn!("all installed packages would be re-configured");
+        for pkg in installed.pkgs {
+            installed.set_dirty(pkg, true);
+        }
+    }
+
+    let graph = graph::build_sync_graph(pkgs, &installed, &mut provider_selection)?;
+    let mut ctx = Context::new(ItemMgr::default());
+    for pkg in installed.pkgs {
+        ctx.set_installed(pkg, true);
+    }
+
+    let mut steps = Vec::with_capacity(graph.len());
+    // packages that would be changed
+    let mut pending = EnumSet::new();
+    for (i, pkg) in graph.iter().copied().enumerate() {
+        ctx.pkg = pkg;
+        ctx.stage.set(Stage::Verify);
+        // dependencies that are not installed yet are assumed to be installed,
+        // which can fail the verification
+        let verified = match pkg.package().verify(&ctx) {
+            Ok(verified) => Some(verified),
+            Err(e) if super::sync::has_pending_dependency(pkg, pending, &provider_selection) => {
+                cu::debug!("cannot verify '{pkg}' before syncing its dependencies: {e:?}");
+                None
+            }
+            Err(e) => {
+                cu::rethrow!(e, "failed to verify '{pkg}'");
+            }
+        };
+        let sync_type = match verified {
+            Some(verified) => SyncType::resolve(verified, pkg, &installed, options.force[pkg]),
+            None => SyncType::Full,
+        };
+        let mut dirtied = EnumSet::new();
+        if sync_type != SyncType::UpToDate {
+            pending.insert(pkg);
+            for pkg2 in graph.iter().skip(i + 1).copied() {
+                if pkg2.package().config_dependencies().contains(pkg) {
+                    installed.set_dirty(pkg2, true);
+                    dirtied.insert(pkg2);
+                }
+            }
+        }
+        ctx.set_installed(pkg, true);
+        let sync_type = match verified {
+            None => format!("{sync_type} (dependencies pending)"),
+            Some(Verified::NotUpToDate) if installed.is_held(pkg) => {
+                format!("{sync_type} (held)")
+            }
+            Some(_) => sync_type.to_string(),
+        };
+        steps.push((pkg, sync_type, dirtied));
+    }
+
+    print_steps(&steps, "sync type");
+    print_providers(&provider_selection);
+
+    finish_plan(pending.len(), check)
+}
+
+/// Print what `shaft remove` would do, without changing anything.
+///
+/// With `check`, error if anything would change
+pub fn remove_plan(
+    packages: &[String],
+    force: bool,
+    cascade: bool,
+    providers: &[String],
+    check: bool,
+) -> cu::Result<()> {
+    let pkgs = graph::parse_pkgs(packages)?;
+    let mut provider_selection = graph::parse_providers(providers)?;
+    let installed = InstallCache::load()?;
+    let mut pkgs = super::remove::rectify_pkgs_to_remove(pkgs, &installed, force);
+    if pkgs.is_empty() {
+        cu::bail!("please specify packages to remove, see `shaft remove -h`");
+    }
+    if cascade {
+        pkgs |= graph::find_binary_dependents_recursive(pkgs, &installed);
+    }
+    graph::disable_provider_prompt();
+    let graph = graph::build_remove_graph(pkgs, &installed, &mut provider_selection)?;
+
+    // same as remove, packages that are not installed are skipped without --force
+    let mut ctx = Context::new(ItemMgr::default());
+    for pkg in installed.pkgs {
+        ctx.set_installed(pkg, true);
+    }
+    let mut actions = Vec::with_capacity(graph.len());
+    let mut uninstalled = EnumSet::new();
+    for pkg in graph.iter().copied() {
+        ctx.pkg = pkg;
+        ctx.stage.set(Stage::Verify);
+        let action = match pkg.package().verify(&ctx) {
+            Ok(Verified::NotInstalled) if !force => {
+                actions.push((pkg, "skip (not installed)"));
+                continue;
+            }
+            Ok(_) => "remove",
+            Err(e) if !force => {
+                cu::rethrow!(e, "failed to verify package status (--force to bypass)");
+            }
+            Err(e) => {
+                cu::warn!("will force uninstall '{pkg}' because of error: {e:?}");
+                "remove (forced)"
+            }
+        };
+        uninstalled.insert(pkg);
+        actions.push((pkg, action));
+    }
+    let config_pkgs =
+        graph::resolve_config_pkgs(EnumSet::new(), uninstalled, &installed).difference(uninstalled);
+
+    let mut steps = Vec::with_capacity(actions.len() + config_pkgs.len());
+    for (pkg, action) in actions {
+        let dirtied = if uninstalled.contains(pkg) {
+            config_pkgs
+                .iter()
+                .filter(|x| x.package().config_dependencies().contains(pkg))
+                .collect()
+        } else {
+            EnumSet::new()
+        };
+        steps.push((pkg, action.to_string(), dirtied));
+    }
+    for pkg in config_pkgs {
+        steps.push((pkg, SyncType::Config.to_string(), EnumSet::new()));
+    }
+
+    print_steps(&steps, "action");
+    print_providers(&provider_selection);
+
+    finish_plan(uninstalled.len(), check)
+}
+
+fn print_steps(steps: &[(PkgId, String, EnumSet<PkgId>)], action_header: &str) {
+    let package_field_width = steps
+        .iter()
+        .map(|(x, _, _)| x.to_str().len())
+        .max()
+        .unwrap_or(10)
+        .max(10);
+    let action_field_width = steps
+        .iter()
+        .map(|(_, x, _)| x.len())
+        .max()
+        .unwrap_or(0)
+        .max(action_header.len());
+    cu::hint!(
+        "{:>package_field_width$} | {:<action_field_width$} | dirties\n------------------------------------------------------------",
+        "package",
+        action_header
+    );
+    for (pkg, action, dirtied) in steps {
+        let dirtied = dirtied.iter().map(|x| x.to_str()).join(", ");
+        cu::print!("{pkg:>package_field_width$} | {action:<action_field_width$} | [{dirtied}]");
+    }
+}
+
+fn print_providers(provider_selection: &EnumMap<BinId, Option<PkgId>>) {
+    let selected = provider_selection
+        .iter()
+        .filter_map(|(bin, pkg)| Some((bin, (*pkg)?)))
+        // only show binaries that have a choice
+        .filter(|(bin, _)| bin.providers().len() > 1)
+        .collect::<Vec<_>>();
+    if selected.is_empty() {
+        return;
+    }
+    cu::hint!("binary providers:");
+    for (bin, pkg) in selected {
+        cu::print!("  {bin} -> {pkg}");
+    }
+}
+
+fn finish_plan(changes: usize, check: bool) -> cu::Result<()> {
+    match changes {
+        0 => {
+            cu::info!("nothing would change");
+            return Ok(());
+        }
+        1 => cu::info!("1 package would change"),
+        x => cu::info!("{x} packages would change"),
+    }
+    if check {
+        cu::bail!("the machine is not converged");
+    }
+    Ok(())
+}
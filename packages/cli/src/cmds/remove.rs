@@ -1,19 +1,74 @@
-use corelib::ItemMgr;
+use std::collections::BTreeMap;
+
+use corelib::{ItemMgr, hmgr};
 use cu::pre::*;
+use enum_map::EnumMap;
 use enumset::EnumSet;
-use registry::{Context, PkgId, Stage, Verified};
+use itertools::Itertools as _;
+use registry::{BinId, Context, PkgId, Stage, Verified};
 
 use crate::graph::{self, InstallCache};
+use crate::history;
 
-pub fn remove(packages: &[String], force: bool) -> cu::Result<()> {
+pub fn remove(
+    packages: &[String],
+    force: bool,
+    cascade: bool,
+    purge: bool,
+    providers: &[String],
+) -> cu::Result<()> {
     let pkgs = graph::parse_pkgs(packages)?;
+    let options = RemoveOptions {
+        force,
+        cascade,
+        purge,
+        providers: graph::parse_providers(providers)?,
+    };
     let mut installed = InstallCache::load()?;
-    let pkgs = rectify_pkgs_to_remove(pkgs, &installed, force);
+    remove_pkgs(pkgs, &mut installed, &options)
+}
+
+/// Options for removing packages
+#[derive(Default)]
+pub struct RemoveOptions {
+    /// Force uninstall when package is in an unclean state
+    pub force: bool,
+    /// Also remove installed packages that depend on the packages
+    pub cascade: bool,
+    /// Also delete files kept after removing, such as the config and downloads
+    pub purge: bool,
+    /// Providers to use for binaries, instead of prompting
+    pub providers: EnumMap<BinId, Option<PkgId>>,
+}
+
+pub fn remove_pkgs(
+    pkgs: EnumSet<PkgId>,
+    installed: &mut InstallCache,
+    options: &RemoveOptions,
+) -> cu::Result<()> {
+    let force = options.force;
+    let providers = &options.providers;
+    let mut pkgs = rectify_pkgs_to_remove(pkgs, installed, force);
     if pkgs.is_empty() {
         cu::bail!("please specify packages to remove, see `shaft remove -h`");
     }
+    if options.cascade {
+        let dependents = graph::find_binary_dependents_recursive(pkgs, installed);
+        if !dependents.is_empty() {
+            let pkgs_string = dependents.iter().map(|x| x.to_str()).join(", ");
+            cu::info!(
+                "the following packages depend on them and will also be removed: [ {pkgs_string} ]"
+            );
+            if !cu::yesno!("continue?")? {
+                cu::bail!("cancelled");
+            }
+            pkgs |= dependents;
+        }
+    }
 
-    let graph = graph::build_remove_graph(pkgs, &installed, &mut Default::default())?;
+    let mut provider_selection = providers.clone();
+    let graph = graph::build_remove_graph(pkgs, installed, &mut provider_selection)?;
+    history::set_graph(&graph);
     match graph.len() {
         0 => cu::bail!("nothing to do"),
         1 => cu::info!("removing 1 package..."),
@@ -33,7 +88,7 @@ pub fn remove(packages: &[String], force: bool) -> cu::Result<()> {
         let pkg = *pkg;
         let package = pkg.package();
         ctx.pkg = pkg;
-        ctx.stage.set(Stage::Verify);
+        history::set_stage(&ctx, Stage::Verify);
         match package.verify(&ctx) {
             Ok(Verified::NotInstalled) => {
                 if !force {
@@ -50,12 +105,13 @@ pub fn remove(packages: &[String], force: bool) -> cu::Result<()> {
             }
         }
         package.pre_uninstall(&ctx)?;
+        history::set_action(pkg, if options.purge { "purge" } else { "remove" });
         to_uninstall.push(pkg);
     }
 
     let len = to_uninstall.len();
     let uninstalled: EnumSet<_> = to_uninstall.iter().copied().collect();
-    let sync_pkgs = graph::resolve_config_pkgs(EnumSet::new(), uninstalled, &installed);
+    let sync_pkgs = graph::resolve_config_pkgs(EnumSet::new(), uninstalled, installed);
     for pkg in sync_pkgs {
         installed.set_dirty(pkg, true);
     }
@@ -67,27 +123,34 @@ pub fn remove(packages: &[String], force: bool) -> cu::Result<()> {
         ctx.set_bar(None);
         installed.remove(pkg);
         ctx.set_installed(pkg, false);
+        if options.purge {
+            cu::check!(purge_package(pkg, installed), "failed to purge '{pkg}'")?;
+        }
         installed.save()?;
     }
 
     // rebuild items if needed (if any package removed their items)
     {
         let bar = cu::progress("rebuilding items").spawn();
-        ctx.stage.set(Stage::Configure);
+        history::set_stage(&ctx, Stage::Configure);
         ctx.items_mut()?.rebuild_items(Some(&bar))?;
         bar.done();
     }
 
     cu::info!("removed {len} packages, configuring...");
+    let sync_options = super::SyncOptions {
+        providers: *providers,
+        ..Default::default()
+    };
     cu::check!(
-        super::sync_pkgs(sync_pkgs, &mut installed),
+        super::sync_pkgs(sync_pkgs, installed, &sync_options),
         "failed to configure packages after removing"
     )?;
 
     Ok(())
 }
 
-fn rectify_pkgs_to_remove(
+pub(crate) fn rectify_pkgs_to_remove(
     pkgs: EnumSet<PkgId>,
     installed: &InstallCache,
     force: bool,
@@ -106,24 +169,72 @@ fn rectify_pkgs_to_remove(
     out
 }
 
-fn do_remove_package(mut ctx: Context) -> cu::Result<Context> {
+/// Delete files of the removed package that are kept by default
+fn purge_package(pkg: PkgId, installed: &mut InstallCache) -> cu::Result<()> {
+    let name = pkg.to_str();
+    cu::debug!("purging '{pkg}'");
+    for file in [
+        hmgr::paths::config_file(name),
+        hmgr::paths::install_old_json(name),
+    ] {
+        if file.exists() {
+            cu::fs::remove(&file)?;
+        }
+    }
+    cu::fs::rec_remove(hmgr::paths::install_old_dir(name))?;
+    cu::fs::rec_remove(hmgr::paths::temp_dir(name))?;
+
+    // resources may be shared with other installed packages
+    let resources = std::mem::take(&mut installed.resources[pkg]);
+    let mut version_cache = BTreeMap::new();
+    for id in resources.version_ids {
+        let is_used = installed
+            .pkgs
+            .iter()
+            .any(|x| installed.resources[x].version_ids.contains(&id));
+        if !is_used {
+            version_cache.insert(id, None);
+        }
+    }
+    hmgr::set_cached_versions(&version_cache)?;
+    if resources.downloads.is_empty() {
+        // installed before the downloads are tracked
+        cu::warn!(
+            "no tracked downloads found for '{pkg}', nothing is removed from the download cache"
+        );
+        cu::hint!("use `shaft cache list` to see which package each cached file is from");
+    }
+    for file in resources.downloads {
+        let is_used = installed
+            .pkgs
+            .iter()
+            .any(|x| installed.resources[x].downloads.contains(&file));
+        let path = hmgr::paths::download_root().join(&file);
+        if !is_used && path.exists() {
+            cu::fs::remove(&path)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn do_remove_package(mut ctx: Context) -> cu::Result<Context> {
     let pkg = ctx.pkg;
     let package = pkg.package();
     let bar = cu::progress(format!("remove '{pkg}'")).spawn();
     ctx.set_bar(Some(&bar));
 
     cu::progress!(bar, "backup");
-    ctx.stage.set(Stage::Backup);
+    history::set_stage(&ctx, Stage::Backup);
     let mut backup_guard = package.backup_guard(&ctx)?;
 
     cu::progress!(bar, "uninstalling");
-    ctx.stage.set(Stage::Uninstall);
+    history::set_stage(&ctx, Stage::Uninstall);
     package.uninstall(&ctx)?;
-    ctx.stage.set(Stage::Configure);
+    history::set_stage(&ctx, Stage::Configure);
     ctx.items_mut()?.remove_package(pkg.to_str(), Some(&bar))?;
 
     cu::progress!(bar, "cleaning");
-    ctx.stage.set(Stage::Clean);
+    history::set_stage(&ctx, Stage::Clean);
     package.clean(&ctx)?;
 
     cu::progress!(bar, "verifying");
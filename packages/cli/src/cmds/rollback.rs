@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use corelib::ItemMgr;
+use corelib::hmgr::{self, ItemEntry};
+use cu::pre::*;
+use registry::{Context, PkgId, Stage, Verified};
+
+use crate::graph::InstallCache;
+use crate::history;
+
+/// State of a package before it was upgraded, saved next to
+/// the previous installation in HOME/install-old
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RollbackRecord {
+    /// Items of the package
+    pub items: Vec<ItemEntry>,
+    /// Version cache entries changed by the upgrade, mapped to the previous value
+    pub version_cache: BTreeMap<String, Option<String>>,
+}
+
+impl RollbackRecord {
+    /// Create the record from the items before the upgrade,
+    /// and the version cache entries of the package before and after the upgrade
+    pub fn new<'a>(
+        items: Vec<ItemEntry>,
+        version_ids: impl IntoIterator<Item = &'a String>,
+        versions_before: &BTreeMap<String, String>,
+        versions_after: &BTreeMap<String, String>,
+    ) -> Self {
+        let mut version_cache = BTreeMap::new();
+        for id in version_ids {
+            let before = versions_before.get(id);
+            if before != versions_after.get(id) {
+                version_cache.insert(id.clone(), before.cloned());
+            }
+        }
+        Self {
+            items,
+            version_cache,
+        }
+    }
+
+    #[cu::context("failed to load rollback record for '{pkg}'")]
+    pub fn load(pkg: PkgId) -> cu::Result<Self> {
+        let content = cu::fs::read_string(hmgr::paths::install_old_json(pkg.to_str()))?;
+        json::parse(&content)
+    }
+
+    #[cu::context("failed to save rollback record for '{pkg}'")]
+    pub fn save(&self, pkg: PkgId) -> cu::Result<()> {
+        cu::fs::write_json_pretty(hmgr::paths::install_old_json(pkg.to_str()), self)
+    }
+}
+
+/// Roll back the package to the previous installation kept in HOME/install-old
+pub fn rollback(package: &str) -> cu::Result<()> {
+    let Some(pkg) = PkgId::from_str(package) else {
+        cu::bail!("cannot find package '{package}'");
+    };
+    let mut installed = InstallCache::load()?;
+    if !installed.pkgs.contains(pkg) {
+        cu::bail!("'{pkg}' is not installed");
+    }
+    let install_dir = hmgr::paths::install_dir(pkg.to_str());
+    let old_install_dir = hmgr::paths::install_old_dir(pkg.to_str());
+    if !old_install_dir.exists() || !hmgr::paths::install_old_json(pkg.to_str()).exists() {
+        cu::bail!("no previous installation of '{pkg}' is kept, cannot roll back");
+    }
+    let record = RollbackRecord::load(pkg)?;
+
+    let items = ItemMgr::load()?;
+    let mut ctx = Context::new(items);
+    for installed_pkg in installed.pkgs {
+        ctx.set_installed(installed_pkg, true);
+    }
+    ctx.pkg = pkg;
+    history::set_action(pkg, "rollback");
+    let bar = cu::progress(format!("rollback '{pkg}'")).spawn();
+    ctx.set_bar(Some(&bar));
+
+    // swap the installations, so the rollback can be undone by rolling back again
+    cu::progress!(bar, "swapping installation");
+    history::set_stage(&ctx, Stage::Install);
+    let temp_dir = hmgr::paths::temp_dir(format!("{pkg}-rollback"));
+    cu::fs::rec_remove(&temp_dir)?;
+    cu::fs::make_dir(hmgr::paths::temp_root())?;
+    if install_dir.exists() {
+        cu::fs::rename(&install_dir, &temp_dir)?;
+    }
+    cu::fs::rename(&old_install_dir, &install_dir)?;
+    if temp_dir.exists() {
+        cu::fs::rename(&temp_dir, &old_install_dir)?;
+    }
+
+    cu::progress!(bar, "restoring items");
+    history::set_stage(&ctx, Stage::Configure);
+    let current_items = ctx.items_mut()?.package_items(pkg.to_str());
+    let versions_before = hmgr::cached_versions()?;
+    ctx.items_mut()?
+        .restore_package(pkg.to_str(), record.items, Some(&bar))?;
+    ctx.items_mut()?.rebuild_items(Some(&bar))?;
+    hmgr::set_cached_versions(&record.version_cache)?;
+    let versions_after = hmgr::cached_versions()?;
+    RollbackRecord::new(
+        current_items,
+        record.version_cache.keys(),
+        &versions_before,
+        &versions_after,
+    )
+    .save(pkg)?;
+
+    // hold the package so the next sync doesn't upgrade it again
+    installed.set_held(pkg, true);
+    installed.save()?;
+
+    cu::progress!(bar, "verifying");
+    history::set_stage(&ctx, Stage::Verify);
+    let report = pkg.package().verify_report(&ctx);
+    match report.verified {
+        Some(Verified::UpToDate | Verified::NotUpToDate) => {}
+        _ => {
+            if let Some(reason) = report.reason {
+                cu::error!("{reason}");
+            }
+            cu::hint!("run `shaft rollback {pkg}` again to undo");
+            cu::bail!("failed to verify '{pkg}' after rolling back");
+        }
+    }
+    installed.info[pkg].version = report.version;
+    installed.save()?;
+    bar.done();
+    cu::info!("rolled back '{pkg}', it is now held at the installed version");
+    cu::hint!("use `shaft unhold {pkg}` to allow upgrading it again");
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+
+use corelib::{ItemMgr, hmgr, opfs};
+use cu::pre::*;
+
+/// State files and directories in HOME saved in a snapshot
+fn state_paths(install: bool) -> Vec<(&'static str, PathBuf)> {
+    let mut paths = vec![
+        ("install_cache.json", hmgr::paths::install_cache_json()),
+        ("version_cache.json", hmgr::paths::version_cache_json()),
+        ("environment.json", hmgr::paths::environment_json()),
+        ("items", hmgr::paths::items_root()),
+        ("config", hmgr::paths::config_root()),
+    ];
+    if install {
+        paths.push(("install", hmgr::paths::install_root()));
+    }
+    paths
+}
+
+/// Save the state files in HOME to a new snapshot, optionally with the installations
+pub fn snapshot_create(install: bool) -> cu::Result<()> {
+    let id = create_snapshot(install)?;
+    cu::info!("created snapshot {id}");
+    Ok(())
+}
+
+/// Save the state files in HOME to a new snapshot and return the id,
+/// which is the time it's created
+#[cu::context("failed to create snapshot")]
+pub(crate) fn create_snapshot(install: bool) -> cu::Result<u64> {
+    cu::fs::make_dir(hmgr::paths::snapshot_root())?;
+    let ids = list_ids()?;
+    // ids are unique even if created in the same second
+    let id = opfs::now_unix().max(ids.last().copied().unwrap_or_default() + 1);
+    let files = state_paths(install)
+        .into_iter()
+        .filter(|(_, path)| path.exists())
+        .map(|(name, path)| (name.to_string(), path))
+        .collect::<Vec<_>>();
+    let bar = cu::progress("creating snapshot").spawn();
+    opfs::targz_files(&snapshot_path(id), &files)?;
+    bar.done();
+    Ok(id)
+}
+
+/// Restore the state files in HOME from a snapshot
+pub fn snapshot_restore(id: u64) -> cu::Result<()> {
+    let path = snapshot_path(id);
+    if !path.exists() {
+        cu::hint!("use `shaft snapshot list` to see the snapshots");
+        cu::bail!("snapshot {id} does not exist");
+    }
+    cu::warn!(
+        "the current state will be replaced with snapshot {id} ({})",
+        opfs::format_utc(id)
+    );
+    if !cu::yesno!("continue?")? {
+        cu::bail!("cancelled");
+    }
+    let current_id = create_snapshot(false)?;
+    cu::info!("saved the current state as snapshot {current_id}");
+
+    let temp_dir = hmgr::paths::temp_dir("snapshot");
+    let result = restore_from(&path, &temp_dir);
+    hmgr::paths::clean_temp_dir("snapshot");
+    cu::check!(
+        result,
+        "failed to restore snapshot {id}, use `shaft snapshot restore {current_id}` to go back"
+    )?;
+
+    let bar = cu::progress("rebuilding items").spawn();
+    ItemMgr::load()?.rebuild_items(Some(&bar))?;
+    bar.done();
+    cu::info!("restored snapshot {id}");
+    cu::hint!("run `shaft status` to check the installed packages");
+    Ok(())
+}
+
+fn restore_from(archive: &Path, temp_dir: &Path) -> cu::Result<()> {
+    let reader = cu::fs::reader(archive)?;
+    // links created with the symlink strategy point to absolute paths in HOME
+    let options = opfs::ExtractOptions {
+        clean: true,
+        file_mode: opfs::FileMode::Preserve,
+        absolute_link_root: Some(hmgr::home().to_path_buf()),
+        ..Default::default()
+    };
+    opfs::untargz_read_with(reader, temp_dir, &options)?;
+    // install is only restored if it's in the snapshot
+    for (name, path) in state_paths(true) {
+        let from = temp_dir.join(name);
+        if !from.exists() {
+            continue;
+        }
+        if path.is_dir() {
+            cu::fs::rec_remove(&path)?;
+        } else if path.exists() {
+            cu::fs::remove(&path)?;
+        }
+        cu::fs::rename(&from, &path)?;
+    }
+    Ok(())
+}
+
+/// List the snapshots
+pub fn snapshot_list() -> cu::Result<()> {
+    let ids = list_ids()?;
+    if ids.is_empty() {
+        cu::info!("no snapshots");
+        return Ok(());
+    }
+    cu::lv::disable_print_time();
+    cu::hint!("{:>10} | {:<23} | size", "id", "time");
+    for id in ids.iter().rev() {
+        let size = snapshot_path(*id)
+            .metadata()
+            .map(|x| x.len())
+            .unwrap_or_default();
+        cu::print!(
+            "{id:>10} | {:<23} | {:.1} MiB",
+            opfs::format_utc(*id),
+            size as f64 / 1048576.0
+        );
+    }
+    Ok(())
+}
+
+/// Remove the oldest snapshots, keeping the latest ones
+pub fn snapshot_prune(keep: usize) -> cu::Result<()> {
+    let ids = list_ids()?;
+    if ids.len() <= keep {
+        cu::info!("nothing to prune");
+        return Ok(());
+    }
+    let count = ids.len() - keep;
+    for id in ids.iter().take(count) {
+        cu::fs::remove(snapshot_path(*id))?;
+    }
+    cu::info!("removed {count} snapshots");
+    Ok(())
+}
+
+fn snapshot_path(id: u64) -> PathBuf {
+    hmgr::paths::snapshot_file(format!("{id}.tar.gz"))
+}
+
+/// Get the ids of the snapshots, oldest first
+fn list_ids() -> cu::Result<Vec<u64>> {
+    let root = hmgr::paths::snapshot_root();
+    if !root.exists() {
+        return Ok(vec![]);
+    }
+    let mut ids = vec![];
+    for entry in cu::fs::read_dir(&root)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|x| x.strip_suffix(".tar.gz"))
+            .and_then(|x| x.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        ids.push(id);
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
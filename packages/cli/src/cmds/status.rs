@@ -0,0 +1,171 @@
+use corelib::ItemMgr;
+use cu::pre::*;
+use registry::{Context, PkgId, Stage, Verified, VerifyReport};
+
+use crate::graph::InstallCache;
+
+/// Exit code of `shaft status` when some packages are not up-to-date,
+/// so scripts can tell drift apart from errors (exit code 1)
+const STATUS_DRIFT_EXIT_CODE: i32 = 2;
+
+/// Result of `shaft status` when all packages verify
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusResult {
+    UpToDate,
+    /// Some packages are not up-to-date
+    Drift,
+}
+
+impl StatusResult {
+    /// Exit code of the process, returned to main so the home lock
+    /// is released before exiting
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::UpToDate => 0,
+            Self::Drift => STATUS_DRIFT_EXIT_CODE,
+        }
+    }
+}
+
+/// Verify all installed packages without syncing, and print the drift.
+///
+/// Errors if any package fails to verify
+pub fn status() -> cu::Result<StatusResult> {
+    let installed = InstallCache::load()?;
+    if installed.pkgs.is_empty() {
+        cu::info!("no packages installed");
+        return Ok(StatusResult::UpToDate);
+    }
+    let mut ctx = Context::new(ItemMgr::default());
+    for pkg in installed.pkgs {
+        ctx.set_installed(pkg, true);
+    }
+
+    let mut rows = Vec::with_capacity(installed.pkgs.len());
+    let mut drifted = 0;
+    let mut errors = 0;
+    {
+        let bar = cu::progress("verifying packages")
+            .total(installed.pkgs.len())
+            .spawn();
+        for pkg in installed.pkgs {
+            cu::progress!(bar += 1, "{pkg}");
+            ctx.pkg = pkg;
+            ctx.stage.set(Stage::Verify);
+            let report = pkg.package().verify_report(&ctx);
+            let row = StatusRow::new(pkg, report, &installed);
+            match row.state {
+                "up-to-date" | "held" => {}
+                "error" => errors += 1,
+                _ => drifted += 1,
+            }
+            rows.push(row);
+        }
+        bar.done();
+    }
+
+    print_rows(&rows);
+
+    if errors > 0 {
+        cu::bail!("failed to verify {errors} package(s)");
+    }
+    match drifted {
+        0 => {
+            cu::info!("all packages are up to date");
+            Ok(StatusResult::UpToDate)
+        }
+        x => {
+            cu::warn!("{x} package(s) are not up to date");
+            cu::hint!("run `shaft sync` to converge");
+            Ok(StatusResult::Drift)
+        }
+    }
+}
+
+struct StatusRow {
+    pkg: PkgId,
+    state: &'static str,
+    version: String,
+    expected_version: String,
+    reason: String,
+}
+
+impl StatusRow {
+    fn new(pkg: PkgId, report: VerifyReport, installed: &InstallCache) -> Self {
+        let mut reason = report.reason.map(|x| x.to_string()).unwrap_or_default();
+        let state = match report.verified {
+            None => "error",
+            Some(Verified::UpToDate) => {
+                if installed.is_dirty(pkg) {
+                    reason = "config was edited".to_string();
+                    "needs-config"
+                } else {
+                    "up-to-date"
+                }
+            }
+            Some(Verified::NeedsConfig) => "needs-config",
+            // held packages are outdated on purpose
+            Some(Verified::NotUpToDate) if installed.is_held(pkg) => "held",
+            Some(Verified::NotUpToDate) => "outdated",
+            Some(Verified::NotInstalled) => "missing",
+        };
+        if installed.is_held(pkg) && reason.is_empty() {
+            reason = "held".to_string();
+        }
+        // only show the first line of errors to keep the table readable
+        if let Some(line) = reason.lines().next() {
+            reason = line.to_string();
+        }
+        Self {
+            pkg,
+            state,
+            version: report.version.unwrap_or_else(|| "-".to_string()),
+            expected_version: report.expected_version.unwrap_or_else(|| "-".to_string()),
+            reason,
+        }
+    }
+}
+
+fn print_rows(rows: &[StatusRow]) {
+    let package_field_width = rows
+        .iter()
+        .map(|x| x.pkg.to_str().len())
+        .max()
+        .unwrap_or(0)
+        .max("package".len());
+    let state_field_width = rows
+        .iter()
+        .map(|x| x.state.len())
+        .max()
+        .unwrap_or(0)
+        .max("state".len());
+    let version_field_width = rows
+        .iter()
+        .map(|x| x.version.len())
+        .max()
+        .unwrap_or(0)
+        .max("installed".len());
+    let expected_field_width = rows
+        .iter()
+        .map(|x| x.expected_version.len())
+        .max()
+        .unwrap_or(0)
+        .max("expected".len());
+    cu::hint!(
+        "{:>package_field_width$} | {:<state_field_width$} | {:<version_field_width$} | {:<expected_field_width$} | reason\n------------------------------------------------------------",
+        "package",
+        "state",
+        "installed",
+        "expected"
+    );
+    for row in rows {
+        cu::print!(
+            "{:>package_field_width$} | {:<state_field_width$} | {:<version_field_width$} | {:<expected_field_width$} | {}",
+            row.pkg.to_str(),
+            row.state,
+            row.version,
+            row.expected_version,
+            row.reason
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use corelib::{ItemMgr, hmgr};
+use cu::pre::*;
+use enum_map::EnumMap;
+use enumset::EnumSet;
+use itertools::Itertools as _;
+use registry::{BinId, Context, PkgId, Stage};
+
+use crate::cmds::SyncOptions;
+use crate::graph::{self, InstallCache, InstallReason};
+use crate::history;
+
+/// Replace the installed provider of a binary with another package
+pub fn swap(bin: &str, new_provider: &str) -> cu::Result<()> {
+    let (bin_id, new_pkg) = graph::parse_provider(bin, new_provider)?;
+    let mut installed = InstallCache::load()?;
+    let Some(old_pkg) = installed.bins[bin_id] else {
+        cu::bail!(
+            "binary '{bin_id}' is not provided by any installed package, use `shaft sync {new_pkg}` to install it"
+        );
+    };
+    if old_pkg == new_pkg {
+        cu::info!("'{bin_id}' is already provided by '{new_pkg}'");
+        return Ok(());
+    }
+    if installed.pkgs.contains(new_pkg) {
+        cu::bail!("'{new_pkg}' is already installed, but does not provide '{bin_id}'");
+    }
+
+    // binaries that other packages need, but will be gone after the swap
+    let old_bins = old_pkg.package().binaries();
+    let new_bins = new_pkg.package().binaries();
+    let lost_bins = old_bins.difference(new_bins);
+    for pkg in installed.pkgs {
+        let needed = pkg.package().binary_dependencies().intersection(lost_bins);
+        if let Some(lost_bin) = needed.iter().next() {
+            cu::bail!(
+                "cannot swap: '{pkg}' depends on binary '{lost_bin}', which is provided by '{old_pkg}' but not '{new_pkg}'"
+            );
+        }
+    }
+    {
+        let mut temp_installed = installed.clone();
+        temp_installed.remove(old_pkg);
+        cu::check!(
+            temp_installed.check_conflicts(new_pkg.into()),
+            "cannot swap '{old_pkg}' for '{new_pkg}'"
+        )?;
+    }
+
+    // packages that use the binaries or the config of the old provider
+    let mut dependents = EnumSet::new();
+    for pkg in installed.pkgs {
+        if !pkg.package().binary_dependencies().is_disjoint(old_bins) {
+            dependents.insert(pkg);
+        }
+    }
+    let mut dependents = graph::resolve_config_pkgs(dependents, old_pkg.into(), &installed);
+    dependents.remove(old_pkg);
+
+    cu::info!("'{old_pkg}' will be replaced by '{new_pkg}'");
+    if !dependents.is_empty() {
+        let pkgs_string = dependents.iter().map(|x| x.to_str()).join(", ");
+        cu::info!("the following packages will be re-configured: [ {pkgs_string} ]");
+    }
+    if !cu::yesno!("continue?")? {
+        cu::bail!("cancelled");
+    }
+
+    for pkg in dependents {
+        installed.set_dirty(pkg, true);
+    }
+    installed.save()?;
+
+    // the new provider is installed for the same reason as the old one
+    let explicit = installed.info[old_pkg].reason == InstallReason::Explicit;
+
+    // uninstall the old provider
+    cu::check!(
+        remove_provider(old_pkg, &mut installed),
+        "failed to remove '{old_pkg}'"
+    )?;
+
+    let result = sync_provider(new_pkg, dependents, explicit, &mut installed);
+    let Err(e) = result else {
+        cu::info!("'{bin_id}' is now provided by '{new_pkg}'");
+        return Ok(());
+    };
+    if installed.pkgs.contains(new_pkg) {
+        // the new provider is installed, only the dependents failed
+        cu::rethrow!(
+            e,
+            "failed to re-configure packages after swapping to '{new_pkg}'"
+        );
+    }
+    cu::error!("failed to install '{new_pkg}': {e:?}");
+    cu::warn!("rolling back to '{old_pkg}'...");
+    clean_failed_provider(new_pkg, &mut installed)?;
+    cu::check!(
+        sync_provider(old_pkg, dependents, explicit, &mut installed),
+        "failed to roll back to '{old_pkg}', sync it manually to restore"
+    )?;
+    cu::bail!("failed to swap '{old_pkg}' for '{new_pkg}', the previous provider is restored");
+}
+
+/// Uninstall the provider and rebuild the items without it
+fn remove_provider(pkg: PkgId, installed: &mut InstallCache) -> cu::Result<()> {
+    let items = ItemMgr::load()?;
+    let mut ctx = Context::new(items);
+    for pkg in installed.pkgs {
+        ctx.set_installed(pkg, true);
+    }
+    ctx.pkg = pkg;
+    history::set_action(pkg, "remove");
+    ctx = super::remove::do_remove_package(ctx)?;
+    ctx.set_bar(None);
+    installed.remove(pkg);
+    installed.save()?;
+    let bar = cu::progress("rebuilding items").spawn();
+    history::set_stage(&ctx, Stage::Configure);
+    ctx.items_mut()?.rebuild_items(Some(&bar))?;
+    bar.done();
+    Ok(())
+}
+
+/// Remove what a failed install of the provider left behind, so the rollback
+/// starts from the same state as before the swap.
+///
+/// The package is uninstalled normally if possible. Otherwise, its items and links,
+/// install directory, temp directory and partial downloads are removed directly.
+fn clean_failed_provider(pkg: PkgId, installed: &mut InstallCache) -> cu::Result<()> {
+    cu::info!("cleaning up '{pkg}'...");
+    let e = match remove_provider(pkg, installed) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    cu::debug!("failed to remove '{pkg}' normally: {e:?}");
+    cu::warn!("'{pkg}' cannot be uninstalled normally, removing its files directly");
+    let mut items = ItemMgr::load()?;
+    items.remove_package(pkg.to_str(), None)?;
+    let bar = cu::progress("rebuilding items").spawn();
+    items.rebuild_items(Some(&bar))?;
+    bar.done();
+    cu::fs::rec_remove(hmgr::paths::install_dir(pkg.to_str()))?;
+    hmgr::paths::clean_temp_dir(pkg.to_str());
+    remove_partial_downloads(pkg, installed)?;
+    installed.remove(pkg);
+    installed.save()?;
+    Ok(())
+}
+
+/// Remove unfinished downloads of the package. Partial downloads of other
+/// packages are kept, so they can be resumed
+fn remove_partial_downloads(pkg: PkgId, installed: &InstallCache) -> cu::Result<()> {
+    let download_root = hmgr::paths::download_root();
+    for name in &installed.resources[pkg].downloads {
+        let path = download_root.join(format!("{name}.part"));
+        if path.exists() {
+            cu::fs::remove(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sync the provider and re-configure its dependents,
+/// with the provider selected for all of its binaries
+fn sync_provider(
+    provider: PkgId,
+    dependents: EnumSet<PkgId>,
+    explicit: bool,
+    installed: &mut InstallCache,
+) -> cu::Result<()> {
+    let mut providers: EnumMap<BinId, Option<PkgId>> = EnumMap::default();
+    for bin_id in provider.package().binaries() {
+        providers[bin_id] = Some(provider);
+    }
+    let mut options = SyncOptions {
+        providers,
+        ..Default::default()
+    };
+    if explicit {
+        options.explicit.insert(provider);
+    }
+    super::sync_pkgs(dependents | provider, installed, &options)
+}
@@ -1,23 +1,87 @@
-use corelib::{ItemMgr, VersionCache, hmgr};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use corelib::hmgr::{self, PackageResources};
+use corelib::{ItemMgr, VersionCache, opfs};
 use cu::pre::*;
+use enum_map::EnumMap;
 use enumset::EnumSet;
-use registry::{Context, PkgId, Stage, Verified};
+use registry::{BinId, Context, ExternalPackage, PackageRestoreGuard, PkgId, Stage, Verified};
 
-use crate::graph::{self, InstallCache};
+use crate::cmds::RollbackRecord;
+use crate::graph::{self, InstallCache, InstallReason};
+use crate::history;
 
-pub fn sync(packages: &[String]) -> cu::Result<()> {
-    let pkgs = graph::parse_pkgs(packages)?;
+pub fn sync(
+    packages: &[String],
+    providers: &[String],
+    force: Option<ForceSync>,
+    all: bool,
+) -> cu::Result<()> {
     let mut installed = InstallCache::load()?;
-    let pkgs = if pkgs.is_empty() {
+    let (pkgs, options) = parse_sync_args(packages, providers, force, all, &installed)?;
+    sync_pkgs(pkgs, &mut installed, &options)
+}
+
+/// Get the packages to sync and the options from the command line arguments
+pub(crate) fn parse_sync_args(
+    packages: &[String],
+    providers: &[String],
+    force: Option<ForceSync>,
+    all: bool,
+    installed: &InstallCache,
+) -> cu::Result<(EnumSet<PkgId>, SyncOptions)> {
+    let pkgs = graph::parse_pkgs(packages)?;
+    let explicit = pkgs;
+    let pkgs = if all || pkgs.is_empty() {
+        if force.is_some() && !all {
+            cu::bail!(
+                "specify the packages to force sync, or use --all for all installed packages"
+            );
+        }
         // sync all installed packages
         installed.pkgs
     } else {
         pkgs
     };
-    sync_pkgs(pkgs, &mut installed)
+    let mut options = SyncOptions {
+        providers: graph::parse_providers(providers)?,
+        explicit,
+        ..Default::default()
+    };
+    for pkg in pkgs {
+        options.force[pkg] = force;
+    }
+    Ok((pkgs, options))
+}
+
+/// Options for syncing packages
+#[derive(Default)]
+pub struct SyncOptions {
+    /// Providers to use for binaries, instead of prompting
+    pub providers: EnumMap<BinId, Option<PkgId>>,
+    /// Values to apply on top of the config files of packages
+    pub config_overrides: EnumMap<PkgId, Option<toml::Table>>,
+    /// Sync types forced for packages, on top of the one derived from verification
+    pub force: EnumMap<PkgId, Option<ForceSync>>,
+    /// Packages requested by the user, which are recorded as explicitly installed
+    pub explicit: EnumSet<PkgId>,
 }
 
-pub fn sync_pkgs(pkgs: EnumSet<PkgId>, installed: &mut InstallCache) -> cu::Result<()> {
+/// Sync type forced from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForceSync {
+    /// Download and install again, even if up-to-date
+    Reinstall,
+    /// Run the config stage again, even if up-to-date
+    Reconfigure,
+}
+
+pub fn sync_pkgs(
+    pkgs: EnumSet<PkgId>,
+    installed: &mut InstallCache,
+    options: &SyncOptions,
+) -> cu::Result<()> {
     if pkgs.is_empty() {
         return Ok(());
     }
@@ -31,11 +95,15 @@ pub fn sync_pkgs(pkgs: EnumSet<PkgId>, installed: &mut InstallCache) -> cu::Resu
             None
         }
     };
-    let core_version_cache = VersionCache::new("registry::CORE_VERSION", registry::CORE_VERSION);
+    let core_version_cache = core_version_cache();
     if let Some(items2) = &mut items {
         let core_version_uptodate = core_version_cache.is_uptodate()?;
         if let Some(false) = core_version_uptodate {
             cu::warn!("core version was bumped - all installed packages will be re-configured");
+            let id = super::snapshot::create_snapshot(false)?;
+            cu::info!(
+                "saved the current state as snapshot {id}, restore it with `shaft snapshot restore {id}` if needed"
+            );
             let bar = cu::progress("removing all config items").spawn();
             items2.remove_all(Some(&bar))?;
             items2.rebuild_items(Some(&bar))?;
@@ -55,7 +123,11 @@ pub fn sync_pkgs(pkgs: EnumSet<PkgId>, installed: &mut InstallCache) -> cu::Resu
             ItemMgr::load()?
         }
     };
-    let graph = graph::build_sync_graph(pkgs, installed, &mut Default::default())?;
+    let mut provider_selection = options.providers.clone();
+    let levels = graph::build_sync_levels(pkgs, installed, &mut provider_selection)?;
+    let graph = levels.iter().flat_map(|x| x.iter()).collect::<Vec<_>>();
+    history::set_graph(&graph);
+    installed.save()?;
     match graph.len() {
         1 => cu::info!("syncing 1 package..."),
         x => cu::info!("syncing {x} packages..."),
@@ -64,110 +136,503 @@ pub fn sync_pkgs(pkgs: EnumSet<PkgId>, installed: &mut InstallCache) -> cu::Resu
     for pkg in installed.pkgs {
         ctx.set_installed(pkg, true);
     }
+    for (pkg, overrides) in &options.config_overrides {
+        ctx.set_config_overrides(pkg, overrides.clone());
+    }
 
-    for (i, pkg) in graph.iter().copied().enumerate() {
-        ctx.pkg = pkg;
-        let result = do_sync_package(ctx, installed);
-        let result = cu::check!(result, "failed to sync '{pkg}'")?;
-        ctx = result.1;
-        ctx.set_bar(None);
-        installed.add(pkg)?;
-        ctx.set_installed(pkg, true);
-        // dirty the config of inverted config dependencies
-        if !matches!(result.0, SyncType::UpToDate) {
-            for pkg2 in graph.iter().skip(i + 1).copied() {
-                if pkg2.package().config_dependencies().contains(pkg) {
-                    installed.set_dirty(pkg2, true);
+    let mut prefetched = prefetch(&mut ctx, &graph, installed, &provider_selection, options)?;
+    installed.save()?;
+
+    // packages in the same level don't depend on each other, so they are
+    // installed in parallel. Verify and configure are
+    // always done in order on the main thread
+    let mut remaining: EnumSet<PkgId> = graph.iter().copied().collect();
+    for level in levels {
+        let mut jobs = Vec::with_capacity(level.len());
+        for pkg in level {
+            ctx.pkg = pkg;
+            PackageResources::begin();
+            let force = options.force[pkg];
+            let prefetched = prefetched[pkg].take();
+            let result = prepare_package(&ctx, installed, force, prefetched);
+            installed.resources[pkg].extend(PackageResources::end());
+            match cu::check!(result, "failed to sync '{pkg}'")? {
+                Some(job) => jobs.push(job),
+                None => {
+                    remaining.remove(pkg);
+                    mark_synced(
+                        pkg,
+                        SyncType::UpToDate,
+                        remaining,
+                        &mut ctx,
+                        installed,
+                        &provider_selection,
+                        options,
+                    )?;
                 }
             }
         }
-        installed.save()?;
+
+        let results = run_parallel(&mut jobs, download_and_install);
+        let mut first_error = None;
+        for (mut job, (result, resources)) in jobs.into_iter().zip(results) {
+            let pkg = job.pkg;
+            hmgr::DownloadIndex::set_owner(&resources.downloads, pkg.to_str(), None);
+            job.downloads.extend(resources.downloads.iter().cloned());
+            installed.resources[pkg].extend(resources);
+            remaining.remove(pkg);
+            if let Err(e) = result {
+                // the old installation is restored when the job is dropped
+                record_error(&mut first_error, pkg, e);
+                continue;
+            }
+            ctx.pkg = pkg;
+            let sync_type = job.sync_type;
+            PackageResources::begin();
+            let result = finish_package(&mut ctx, job, installed);
+            installed.resources[pkg].extend(PackageResources::end());
+            ctx.set_bar(None);
+            if let Err(e) = result {
+                record_error(&mut first_error, pkg, e);
+                continue;
+            }
+            mark_synced(
+                pkg,
+                sync_type,
+                remaining,
+                &mut ctx,
+                installed,
+                &provider_selection,
+                options,
+            )?;
+        }
+        if let Some((pkg, e)) = first_error {
+            cu::rethrow!(e, "failed to sync '{pkg}'");
+        }
     }
 
     Ok(())
 }
 
-fn do_sync_package(
-    mut ctx: Context,
+/// Keep the first error to return after the level is done, and print the others
+fn record_error<E: std::fmt::Debug>(first_error: &mut Option<(PkgId, E)>, pkg: PkgId, e: E) {
+    match first_error {
+        None => *first_error = Some((pkg, e)),
+        Some(_) => cu::error!("failed to sync '{pkg}': {e:?}"),
+    }
+}
+
+/// A package that needs to be synced
+struct SyncJob {
+    pkg: PkgId,
+    sync_type: SyncType,
+    bar: Arc<cu::ProgressBar>,
+    /// Context to run the download and install stages, which may be on another thread
+    ctx: Context,
+    /// Restores the old installation when dropped, unless the sync is successful
+    backup_guard: Option<PackageRestoreGuard<'static>>,
+    /// The version cache before the upgrade, to be able to roll back
+    versions_before: Option<BTreeMap<String, String>>,
+    /// If the download stage is already done
+    prefetched: bool,
+    /// Files in the download cache used by the download stage
+    downloads: BTreeSet<String>,
+}
+
+/// Update the install cache after the package is synced
+fn mark_synced(
+    pkg: PkgId,
+    sync_type: SyncType,
+    remaining: EnumSet<PkgId>,
+    ctx: &mut Context,
     installed: &mut InstallCache,
-) -> cu::Result<(SyncType, Context)> {
+    provider_selection: &EnumMap<BinId, Option<PkgId>>,
+    options: &SyncOptions,
+) -> cu::Result<()> {
+    let newly_installed = !installed.pkgs.contains(pkg);
+    installed.add(pkg)?;
+    installed.save_provider_selection(pkg, provider_selection);
+    ctx.set_installed(pkg, true);
+    let info = &mut installed.info[pkg];
+    if options.explicit.contains(pkg) {
+        info.reason = InstallReason::Explicit;
+    } else if newly_installed {
+        info.reason = InstallReason::Dependency;
+    }
+    if newly_installed || matches!(sync_type, SyncType::Full | SyncType::FullWithBackup) {
+        info.timestamp = opfs::now_unix();
+    }
+    // dirty the config of inverted config dependencies
+    if !matches!(sync_type, SyncType::UpToDate) {
+        for pkg2 in remaining {
+            if pkg2.package().config_dependencies().contains(pkg) {
+                installed.set_dirty(pkg2, true);
+            }
+        }
+    }
+    installed.save()
+}
+
+/// Verify the package and determine what needs to be done. Returns `None` if up-to-date.
+///
+/// The package is only verified if it's not verified by the prefetch
+fn prepare_package(
+    ctx: &Context,
+    installed: &InstallCache,
+    force: Option<ForceSync>,
+    prefetched: Option<Prefetched>,
+) -> cu::Result<Option<SyncJob>> {
     let pkg = ctx.pkg;
     let package = ctx.pkg.package();
-    ctx.stage.set(Stage::Verify);
+    history::set_stage(ctx, Stage::Verify);
 
-    let sync_type = match package.verify(&ctx)? {
-        Verified::NotInstalled => SyncType::Full,
-        Verified::NotUpToDate => SyncType::FullWithBackup,
-        Verified::NeedsConfig => SyncType::Config,
-        Verified::UpToDate => {
-            // if the pkg is not installed yet (meaning never configured),
-            // configure it even if the binaries are installed already
-            //
-            // and reconfigure if the config is dirtied
-            if !installed.pkgs.contains(pkg) || installed.is_dirty(pkg) {
-                SyncType::Config
-            } else {
-                SyncType::UpToDate
-            }
-        }
+    let (verified, prefetched) = match prefetched {
+        Some(Prefetched {
+            verified: Some(verified),
+            downloads,
+        }) => (verified, downloads),
+        // verified now that the dependencies are synced
+        Some(Prefetched {
+            verified: None,
+            downloads,
+        }) => (package.verify(ctx)?, downloads),
+        None => (package.verify(ctx)?, None),
     };
+    if verified == Verified::NotUpToDate && installed.is_held(pkg) {
+        if force == Some(ForceSync::Reinstall) {
+            cu::warn!(
+                "'{pkg}' is not reinstalled because it is held, reinstalling would upgrade it"
+            );
+        } else {
+            cu::warn!("'{pkg}' is outdated, but not upgraded because it is held");
+        }
+        cu::hint!("use `shaft unhold {pkg}` to allow upgrading it");
+    }
+    let sync_type = SyncType::resolve(verified, pkg, installed, force);
+    history::set_action(pkg, sync_type);
 
-    let (bar, mut backup_guard) = match sync_type {
+    let bar = match sync_type {
         SyncType::UpToDate => {
             cu::info!("up to date: '{pkg}'");
-            return Ok((sync_type, ctx));
+            return Ok(None);
         }
         SyncType::Config => {
             cu::debug!("sync type for '{pkg}': config");
-            let bar = cu::progress(format!("config '{pkg}'")).spawn();
-            ctx.set_bar(Some(&bar));
-            (bar, None)
+            cu::progress(format!("config '{pkg}'")).spawn()
         }
         SyncType::FullWithBackup => {
             cu::debug!("sync type for '{pkg}': full-backup");
-            let bar = cu::progress(format!("sync '{pkg}'")).spawn();
-            ctx.set_bar(Some(&bar));
-
-            cu::progress!(bar, "backup");
-            ctx.stage.set(Stage::Backup);
-            (bar, Some(package.backup_guard(&ctx)?))
+            cu::progress(format!("sync '{pkg}'")).spawn()
         }
         SyncType::Full => {
             cu::debug!("sync type for '{pkg}': full");
-            let bar = cu::progress(format!("sync '{pkg}'")).spawn();
-            ctx.set_bar(Some(&bar));
-            (bar, None)
+            cu::progress(format!("sync '{pkg}'")).spawn()
         }
     };
+    let mut job_ctx = ctx.fork(pkg);
+    job_ctx.set_bar(Some(&bar));
 
-    if !matches!(sync_type, SyncType::Config) {
-        cu::progress!(bar, "downloading");
-        ctx.stage.set(Stage::Download);
-        package.download(&ctx)?;
+    let (backup_guard, versions_before) = match sync_type {
+        SyncType::FullWithBackup => {
+            cu::progress!(bar, "backup");
+            history::set_stage(&job_ctx, Stage::Backup);
+            let versions_before = hmgr::cached_versions()?;
+            (Some(package.backup_guard(&job_ctx)?), Some(versions_before))
+        }
+        _ => (None, None),
+    };
+
+    Ok(Some(SyncJob {
+        pkg,
+        sync_type,
+        bar,
+        ctx: job_ctx,
+        backup_guard,
+        versions_before,
+        prefetched: prefetched.is_some(),
+        downloads: prefetched.unwrap_or_default(),
+    }))
+}
+
+/// Run the function on each item in parallel, returning the result
+/// and the resources used for each item
+fn run_parallel<T: Send>(
+    items: &mut [T],
+    f: fn(&mut T) -> cu::Result<()>,
+) -> Vec<(cu::Result<()>, PackageResources)> {
+    let run = move |item: &mut T| {
+        PackageResources::begin();
+        let result = f(item);
+        (result, PackageResources::end())
+    };
+    if items.len() <= 1 {
+        return items.iter_mut().map(run).collect();
+    }
+    std::thread::scope(|s| {
+        let handles = items
+            .iter_mut()
+            .map(|item| s.spawn(move || run(item)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|x| x.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
 
-        cu::progress!(bar, "installing");
-        ctx.stage.set(Stage::Install);
-        package.install(&ctx)?;
+/// Run the download and install stages, which don't use config items
+fn download_and_install(job: &mut SyncJob) -> cu::Result<()> {
+    if matches!(job.sync_type, SyncType::Config) {
+        return Ok(());
+    }
+    let package = job.pkg.package();
+    let ctx = &job.ctx;
+    if !job.prefetched {
+        cu::progress!(job.bar, "downloading");
+        history::set_stage(ctx, Stage::Download);
+        package.download(ctx)?;
     }
 
+    cu::progress!(job.bar, "installing");
+    history::set_stage(ctx, Stage::Install);
+    package.install(ctx)?;
+    Ok(())
+}
+
+/// Result of verifying a package before anything is synced
+struct Prefetched {
+    /// `None` if the package cannot be verified before its dependencies are synced
+    verified: Option<Verified>,
+    /// Files downloaded, if the download stage is already done
+    downloads: Option<BTreeSet<String>>,
+}
+
+/// Verify the packages, and run the download stage of the ones that need to be installed,
+/// so nothing is installed if any download fails.
+///
+/// Packages that fail to verify while their dependencies are going to be synced
+/// are downloaded as if they need to be installed, and are verified again when synced.
+/// Other verification errors fail the sync before anything is installed
+fn prefetch(
+    ctx: &mut Context,
+    graph: &[PkgId],
+    installed: &mut InstallCache,
+    provider_selection: &EnumMap<BinId, Option<PkgId>>,
+    options: &SyncOptions,
+) -> cu::Result<EnumMap<PkgId, Option<Prefetched>>> {
+    let mut prefetched: EnumMap<PkgId, Option<Prefetched>> = EnumMap::default();
+    let mut pkgs = EnumSet::new();
+    // packages that will be changed by the sync
+    let mut pending = EnumSet::new();
+    let mut not_cached = EnumSet::new();
+    for pkg in graph.iter().copied() {
+        ctx.pkg = pkg;
+        history::set_stage(ctx, Stage::Verify);
+        PackageResources::begin();
+        let report = pkg.package().verify_report(ctx);
+        installed.resources[pkg].extend(PackageResources::end());
+        let verified = match report.verified {
+            Some(verified) => Some(verified),
+            None if has_pending_dependency(pkg, pending, provider_selection) => {
+                cu::debug!(
+                    "cannot verify '{pkg}' before syncing its dependencies, assuming it needs to be installed"
+                );
+                None
+            }
+            None => {
+                let reason = report.reason.map(|x| x.to_string()).unwrap_or_default();
+                cu::bail!("failed to verify '{pkg}': {reason}");
+            }
+        };
+        let sync_type = match verified {
+            Some(verified) => SyncType::resolve(verified, pkg, installed, options.force[pkg]),
+            None => SyncType::Full,
+        };
+        if !matches!(sync_type, SyncType::UpToDate) {
+            pending.insert(pkg);
+        }
+        if matches!(sync_type, SyncType::Full | SyncType::FullWithBackup) {
+            pkgs.insert(pkg);
+            // packages from other package managers are not downloaded by shaft
+            if hmgr::is_offline() {
+                check_external_cached(pkg, &report.external, verified.is_none(), &mut not_cached);
+            }
+        }
+        prefetched[pkg] = Some(Prefetched {
+            verified,
+            downloads: None,
+        });
+    }
+    if !not_cached.is_empty() {
+        cu::hint!("sync them while online to install them");
+        let pkgs_string = not_cached
+            .iter()
+            .map(|x| x.to_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        cu::bail!("the following packages cannot be synced offline: [ {pkgs_string} ]");
+    }
+    if pkgs.is_empty() {
+        return Ok(prefetched);
+    }
+    cu::info!("downloading {} package(s)...", pkgs.len());
+    let downloads = cu::check!(
+        download_pkgs(ctx, pkgs, installed),
+        "failed to download packages, nothing is installed"
+    )?;
+    for pkg in pkgs {
+        if let Some(x) = &mut prefetched[pkg] {
+            x.downloads = Some(downloads[pkg].clone());
+        }
+    }
+    Ok(prefetched)
+}
+
+/// Check that the packages from other package managers needed by the package
+/// are in their local cache, so the package can be synced offline
+fn check_external_cached(
+    pkg: PkgId,
+    external: &[ExternalPackage],
+    unverified: bool,
+    not_cached: &mut EnumSet<PkgId>,
+) {
+    if unverified && external.is_empty() {
+        // verify failed before checking them
+        cu::warn!(
+            "'{pkg}' cannot be verified before its dependencies are synced, it may need packages that are not in the local cache"
+        );
+    }
+    for external in external {
+        if !external.is_cached() {
+            cu::error!("'{pkg}' needs {external}, which is not in the local cache");
+            not_cached.insert(pkg);
+            continue;
+        }
+        if matches!(external, ExternalPackage::Cargo(_)) {
+            cu::warn!(
+                "'{pkg}' needs {external}, only the crate is checked to be in the local cache, not its dependencies"
+            );
+        }
+    }
+}
+
+/// Check if any dependency of the package is changed by the sync
+pub(crate) fn has_pending_dependency(
+    pkg: PkgId,
+    pending: EnumSet<PkgId>,
+    provider_selection: &EnumMap<BinId, Option<PkgId>>,
+) -> bool {
+    let package = pkg.package();
+    let bin_providers = package
+        .binary_dependencies()
+        .iter()
+        .filter_map(|x| provider_selection[x]);
+    bin_providers
+        .chain(package.config_dependencies())
+        .any(|x| pending.contains(x))
+}
+
+/// Run the download stage of the packages in parallel.
+///
+/// Returns the files in the download cache used by each package
+pub(crate) fn download_pkgs(
+    ctx: &Context,
+    pkgs: EnumSet<PkgId>,
+    installed: &mut InstallCache,
+) -> cu::Result<EnumMap<PkgId, BTreeSet<String>>> {
+    let mut forks = pkgs
+        .iter()
+        .map(|pkg| {
+            let bar = cu::progress(format!("download '{pkg}'")).spawn();
+            let mut fork = ctx.fork(pkg);
+            fork.set_bar(Some(&bar));
+            (fork, bar)
+        })
+        .collect::<Vec<_>>();
+    let results = run_parallel(&mut forks, |(fork, bar)| {
+        history::set_stage(fork, Stage::Download);
+        fork.pkg.package().download(fork)?;
+        bar.done();
+        Ok(())
+    });
+    let mut failed = vec![];
+    let mut downloads: EnumMap<PkgId, BTreeSet<String>> = EnumMap::default();
+    for ((fork, _), (result, resources)) in forks.iter().zip(results) {
+        hmgr::DownloadIndex::set_owner(&resources.downloads, fork.pkg.to_str(), None);
+        downloads[fork.pkg] = resources.downloads.clone();
+        installed.resources[fork.pkg].extend(resources);
+        if let Err(e) = result {
+            cu::error!("failed to download '{}': {e:?}", fork.pkg);
+            failed.push(fork.pkg.to_str());
+        }
+    }
+    if failed.is_empty() {
+        return Ok(downloads);
+    }
+    let pkgs_string = failed.join(", ");
+    if hmgr::is_offline() {
+        cu::hint!(
+            "use `shaft fetch {}` while online to download them",
+            failed.join(" ")
+        );
+        cu::bail!("the following packages cannot be synced offline: [ {pkgs_string} ]");
+    }
+    cu::bail!("failed to download: [ {pkgs_string} ]");
+}
+
+/// Run the configure, clean and verify stages on the main context
+fn finish_package(
+    ctx: &mut Context,
+    mut job: SyncJob,
+    installed: &mut InstallCache,
+) -> cu::Result<()> {
+    let pkg = job.pkg;
+    let package = pkg.package();
+    let bar = &job.bar;
+    ctx.set_bar(Some(bar));
+
     cu::progress!(bar, "configuring");
-    ctx.stage.set(Stage::Configure);
-    ctx.items_mut()?.remove_package(pkg.to_str(), Some(&bar))?;
-    package.configure(&ctx)?;
-    ctx.items_mut()?.rebuild_items(Some(&bar))?;
+    history::set_stage(ctx, Stage::Configure);
+    let items_before = ctx.items_mut()?.package_items(pkg.to_str());
+    ctx.items_mut()?.remove_package(pkg.to_str(), Some(bar))?;
+    package.configure(ctx)?;
+    ctx.items_mut()?.rebuild_items(Some(bar))?;
     installed.set_dirty(pkg, false);
 
     cu::progress!(bar, "cleaning");
-    ctx.stage.set(Stage::Clean);
-    package.clean(&ctx)?;
+    history::set_stage(ctx, Stage::Clean);
+    package.clean(ctx)?;
 
     cu::progress!(bar, "verifying");
-    match package.verify(&ctx)? {
+    let report = package.verify_report(ctx);
+    let Some(verified) = report.verified else {
+        let reason = report.reason.map(|x| x.to_string()).unwrap_or_default();
+        cu::bail!("failed to verify '{pkg}' after installation: {reason}");
+    };
+    match verified {
+        // held packages are not upgraded, so they may still be outdated
+        Verified::NotUpToDate if installed.is_held(pkg) => {
+            bar.done();
+            installed.info[pkg].version = report.version;
+        }
         Verified::UpToDate => {
             bar.done();
-            if let Some(mut x) = backup_guard.take() {
+            hmgr::DownloadIndex::set_owner(&job.downloads, pkg.to_str(), report.version.as_deref());
+            installed.info[pkg].version = report.version;
+            if let Some(mut x) = job.backup_guard.take() {
                 x.clear();
             }
+            // keep what is needed to roll back to the old installation
+            let versions_before = job
+                .versions_before
+                .take()
+                .filter(|_| ctx.install_old_dir().exists());
+            if let Some(versions_before) = versions_before {
+                let versions_after = hmgr::cached_versions()?;
+                let version_ids = &installed.resources[pkg].version_ids;
+                RollbackRecord::new(items_before, version_ids, &versions_before, &versions_after)
+                    .save(pkg)?;
+            }
         }
         _ => {
             cu::hint!(
@@ -186,18 +651,135 @@ such as pacman and cargo.
             cu::bail!("failed to verify '{pkg}' after installation");
         }
     }
-    drop(backup_guard);
 
-    Ok((sync_type, ctx))
+    Ok(())
 }
 
-enum SyncType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum SyncType {
     /// already up-to-date, nothing to do
+    #[display("up-to-date")]
     UpToDate,
     /// Just run the config stage to refresh the config
+    #[display("config")]
     Config,
     /// Full sync - download and install
+    #[display("full")]
     Full,
     /// Full sync - download and install, and also backup the old installation
+    #[display("full-backup")]
     FullWithBackup,
 }
+impl SyncType {
+    /// Determine what needs to be done for the package from the verification result
+    /// and the sync type forced from the command line
+    pub fn resolve(
+        verified: Verified,
+        pkg: PkgId,
+        installed: &InstallCache,
+        force: Option<ForceSync>,
+    ) -> Self {
+        let sync_type = match verified {
+            Verified::NotInstalled => Self::Full,
+            // held packages are not upgraded
+            Verified::NotUpToDate if !installed.is_held(pkg) => Self::FullWithBackup,
+            Verified::NeedsConfig => Self::Config,
+            Verified::NotUpToDate | Verified::UpToDate => {
+                // if the pkg is not installed yet (meaning never configured),
+                // configure it even if the binaries are installed already
+                //
+                // and reconfigure if the config is dirtied
+                if !installed.pkgs.contains(pkg) || installed.is_dirty(pkg) {
+                    Self::Config
+                } else {
+                    Self::UpToDate
+                }
+            }
+        };
+        // reinstalling an outdated held package would upgrade it
+        let held_outdated = verified == Verified::NotUpToDate && installed.is_held(pkg);
+        match (force, sync_type) {
+            (Some(ForceSync::Reinstall), Self::UpToDate | Self::Config) if !held_outdated => {
+                Self::FullWithBackup
+            }
+            (Some(ForceSync::Reconfigure), Self::UpToDate) => Self::Config,
+            _ => sync_type,
+        }
+    }
+}
+
+/// Version cache for the core config version. When bumped, all installed
+/// packages are re-configured
+pub fn core_version_cache() -> VersionCache {
+    VersionCache::new("registry::CORE_VERSION", registry::CORE_VERSION)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_sync_type() {
+        use ForceSync::*;
+        use SyncType::*;
+        use Verified::*;
+        #[rustfmt::skip]
+        let table = [
+            // verified,    held,  force,             expected
+            (NotInstalled, false, None,              Full),
+            (NotInstalled, false, Some(Reinstall),   Full),
+            (NotInstalled, false, Some(Reconfigure), Full),
+            (NotInstalled, true,  None,              Full),
+            (NotInstalled, true,  Some(Reinstall),   Full),
+            (NotInstalled, true,  Some(Reconfigure), Full),
+            (NotUpToDate,  false, None,              FullWithBackup),
+            (NotUpToDate,  false, Some(Reinstall),   FullWithBackup),
+            (NotUpToDate,  false, Some(Reconfigure), FullWithBackup),
+            (NotUpToDate,  true,  None,              SyncType::UpToDate),
+            (NotUpToDate,  true,  Some(Reinstall),   SyncType::UpToDate),
+            (NotUpToDate,  true,  Some(Reconfigure), Config),
+            (NeedsConfig,  false, None,              Config),
+            (NeedsConfig,  false, Some(Reinstall),   FullWithBackup),
+            (NeedsConfig,  false, Some(Reconfigure), Config),
+            (NeedsConfig,  true,  None,              Config),
+            (NeedsConfig,  true,  Some(Reinstall),   FullWithBackup),
+            (NeedsConfig,  true,  Some(Reconfigure), Config),
+            (Verified::UpToDate, false, None,              SyncType::UpToDate),
+            (Verified::UpToDate, false, Some(Reinstall),   FullWithBackup),
+            (Verified::UpToDate, false, Some(Reconfigure), Config),
+            (Verified::UpToDate, true,  None,              SyncType::UpToDate),
+            (Verified::UpToDate, true,  Some(Reinstall),   FullWithBackup),
+            (Verified::UpToDate, true,  Some(Reconfigure), Config),
+        ];
+        let pkg = PkgId::CargoBinstall;
+        for (verified, held, force, expected) in table {
+            let mut installed = InstallCache::default();
+            installed.add(pkg).unwrap();
+            installed.set_held(pkg, held);
+            assert_eq!(
+                SyncType::resolve(verified, pkg, &installed, force),
+                expected,
+                "verified={verified:?}, held={held}, force={force:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_sync_type_not_configured() {
+        let pkg = PkgId::CargoBinstall;
+        // binaries are installed, but the package is never configured
+        let installed = InstallCache::default();
+        assert_eq!(
+            SyncType::resolve(Verified::UpToDate, pkg, &installed, None),
+            SyncType::Config
+        );
+        // config was edited
+        let mut installed = InstallCache::default();
+        installed.add(pkg).unwrap();
+        installed.set_dirty(pkg, true);
+        assert_eq!(
+            SyncType::resolve(Verified::UpToDate, pkg, &installed, None),
+            SyncType::Config
+        );
+    }
+}
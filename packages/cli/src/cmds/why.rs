@@ -0,0 +1,57 @@
+use corelib::opfs;
+use cu::pre::*;
+use enumset::EnumSet;
+use registry::PkgId;
+
+use crate::graph::{self, Dependency, InstallCache};
+
+/// Print the chains of installed packages that depend on the package
+pub fn why(package: &str) -> cu::Result<()> {
+    let Some(pkg) = PkgId::from_str(package) else {
+        cu::bail!("cannot find package '{package}'");
+    };
+    let installed = InstallCache::load()?;
+    if !installed.pkgs.contains(pkg) {
+        cu::warn!("'{pkg}' is not installed");
+    } else {
+        let info = &installed.info[pkg];
+        match &info.version {
+            Some(version) => cu::info!("'{pkg}' {version} is installed ({})", info.reason),
+            None => cu::info!("'{pkg}' is installed ({})", info.reason),
+        }
+        if info.timestamp != 0 {
+            cu::info!("installed at {}", opfs::format_utc(info.timestamp));
+        }
+    }
+    let dependents = graph::find_dependents(pkg, &installed);
+    if dependents.is_empty() {
+        cu::info!("no installed package depends on '{pkg}'");
+        return Ok(());
+    }
+    cu::lv::disable_print_time();
+    cu::print!("{pkg}");
+    print_dependents(pkg, &installed, 1, &mut pkg.into());
+    Ok(())
+}
+
+fn print_dependents(
+    pkg: PkgId,
+    installed: &InstallCache,
+    depth: usize,
+    visited: &mut EnumSet<PkgId>,
+) {
+    let indent = "  ".repeat(depth);
+    for (dependent, dependency) in graph::find_dependents(pkg, installed) {
+        let reason = match dependency {
+            Dependency::Binary(bin_id) => format!("needs binary '{bin_id}'"),
+            Dependency::Config => "config depends on it".to_string(),
+        };
+        if !visited.insert(dependent) {
+            cu::print!("{indent}<- {dependent} ({reason}) ...");
+            continue;
+        }
+        cu::print!("{indent}<- {dependent} ({reason})");
+        print_dependents(dependent, installed, depth + 1, visited);
+        visited.remove(dependent);
+    }
+}
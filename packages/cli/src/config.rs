@@ -5,6 +5,10 @@ use cu::pre::*;
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
     pub windows: WindowsConfig,
+    #[serde(default)]
+    pub network: hmgr::NetworkConfig,
+    #[serde(default)]
+    pub link: hmgr::LinkConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,5 +26,6 @@ pub fn load_config() -> cu::Result<Config> {
     }
     let config_content = cu::fs::read_string(&path)?;
     let config = toml::parse::<Config>(&config_content)?;
+    config.network.validate()?;
     Ok(config)
 }
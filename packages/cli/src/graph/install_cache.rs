@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use corelib::hmgr;
+use corelib::hmgr::{self, PackageResources};
 use cu::pre::*;
 use enum_map::EnumMap;
 use enumset::EnumSet;
@@ -14,6 +14,44 @@ pub struct InstallCache {
     pub dirty: EnumSet<PkgId>,
     /// Binaries available mapping to the package that provides it
     pub bins: EnumMap<BinId, Option<PkgId>>,
+    /// Provider selected for binaries with multiple providers,
+    /// used instead of prompting the next time
+    pub providers: EnumMap<BinId, Option<PkgId>>,
+    /// Set of packages held at the installed version, which are not upgraded by sync
+    pub held: EnumSet<PkgId>,
+    /// Resources used by packages, kept after the package is removed until it's purged
+    pub resources: EnumMap<PkgId, PackageResources>,
+    /// Information about installed packages
+    pub info: EnumMap<PkgId, InstallInfo>,
+}
+
+/// Information about an installed package
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstallInfo {
+    /// Why the package is installed
+    #[serde(default)]
+    pub reason: InstallReason,
+    /// The version verified after the package was last synced,
+    /// if the package checks its version
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Seconds since UNIX epoch when the package was last installed
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+/// Why a package is installed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallReason {
+    /// Requested by the user. Packages installed before the reason is recorded
+    /// are also treated as explicit, so they are never auto-removed
+    #[default]
+    #[display("explicit")]
+    Explicit,
+    /// Installed as a dependency of another package
+    #[display("dependency")]
+    Dependency,
 }
 
 impl InstallCache {
@@ -89,6 +127,8 @@ impl InstallCache {
 
     pub fn remove(&mut self, pkg: PkgId) {
         self.dirty.remove(pkg);
+        self.held.remove(pkg);
+        self.info[pkg] = InstallInfo::default();
         if !self.pkgs.remove(pkg) {
             // was not installed, no-op
             return;
@@ -100,6 +140,41 @@ impl InstallCache {
         }
     }
 
+    /// Remember the provider selected for binaries that have multiple providers,
+    /// for the binaries provided by the package. Called after the package is synced,
+    /// so a failed sync doesn't change the selection
+    pub fn save_provider_selection(
+        &mut self,
+        pkg: PkgId,
+        provider_selection: &EnumMap<BinId, Option<PkgId>>,
+    ) {
+        for bin_id in pkg.package().binaries() {
+            if provider_selection[bin_id] == Some(pkg) && bin_id.providers().len() > 1 {
+                self.providers[bin_id] = Some(pkg);
+            }
+        }
+    }
+
+    /// Get the installed packages that are explicitly installed
+    pub fn explicit_pkgs(&self) -> EnumSet<PkgId> {
+        self.pkgs
+            .iter()
+            .filter(|x| self.info[*x].reason == InstallReason::Explicit)
+            .collect()
+    }
+
+    pub fn is_held(&self, pkg: PkgId) -> bool {
+        self.held.contains(pkg)
+    }
+
+    pub fn set_held(&mut self, pkg: PkgId, held: bool) {
+        if held {
+            self.held.insert(pkg);
+        } else {
+            self.held.remove(pkg);
+        }
+    }
+
     pub fn is_dirty(&self, pkg: PkgId) -> bool {
         self.dirty.contains(pkg)
     }
@@ -131,6 +206,31 @@ impl From<&InstallCacheJson> for InstallCache {
                 dirty.insert(pkg_id);
             }
         }
+        let mut held = EnumSet::new();
+        for name in &value.held {
+            let Some(pkg_id) = PkgId::from_str(name) else {
+                continue;
+            };
+            if pkgs.contains(pkg_id) {
+                held.insert(pkg_id);
+            }
+        }
+        let mut info: EnumMap<PkgId, InstallInfo> = EnumMap::default();
+        for (name, pkg_info) in &value.info {
+            let Some(pkg_id) = PkgId::from_str(name) else {
+                continue;
+            };
+            if pkgs.contains(pkg_id) {
+                info[pkg_id] = pkg_info.clone();
+            }
+        }
+        let mut resources: EnumMap<PkgId, PackageResources> = EnumMap::default();
+        for (name, pkg_resources) in &value.resources {
+            let Some(pkg_id) = PkgId::from_str(name) else {
+                continue;
+            };
+            resources[pkg_id] = pkg_resources.clone();
+        }
         let mut bins: EnumMap<BinId, Option<PkgId>> = EnumMap::default();
         for (bin, pkg) in &value.bins {
             let Some(bin_id) = BinId::from_str(bin) else {
@@ -148,7 +248,29 @@ impl From<&InstallCacheJson> for InstallCache {
             }
             bins[bin_id] = Some(pkg_id);
         }
-        Self { pkgs, dirty, bins }
+        let mut providers: EnumMap<BinId, Option<PkgId>> = EnumMap::default();
+        for (bin, pkg) in &value.providers {
+            let Some(bin_id) = BinId::from_str(bin) else {
+                continue;
+            };
+            let Some(pkg_id) = PkgId::from_str(pkg) else {
+                continue;
+            };
+            // ensures the package still provides the binary
+            if !bin_id.providers().contains(pkg_id) {
+                continue;
+            }
+            providers[bin_id] = Some(pkg_id);
+        }
+        Self {
+            pkgs,
+            dirty,
+            bins,
+            providers,
+            held,
+            resources,
+            info,
+        }
     }
 }
 impl From<InstallCacheJson> for InstallCache {
@@ -162,12 +284,37 @@ impl From<&InstallCache> for InstallCacheJson {
     fn from(value: &InstallCache) -> Self {
         let pkgs = value.pkgs.iter().map(|x| x.to_string()).collect();
         let dirty = value.dirty.iter().map(|x| x.to_string()).collect();
+        let held = value.held.iter().map(|x| x.to_string()).collect();
         let bins = value
             .bins
             .iter()
             .filter_map(|(k, v)| Some((k.to_string(), v.as_ref().copied()?.to_string())))
             .collect();
-        Self { pkgs, dirty, bins }
+        let providers = value
+            .providers
+            .iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v.as_ref().copied()?.to_string())))
+            .collect();
+        let resources = value
+            .resources
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect();
+        let info = value
+            .pkgs
+            .iter()
+            .map(|x| (x.to_string(), value.info[x].clone()))
+            .collect();
+        Self {
+            pkgs,
+            dirty,
+            bins,
+            providers,
+            held,
+            resources,
+            info,
+        }
     }
 }
 impl From<InstallCache> for InstallCacheJson {
@@ -186,4 +333,16 @@ struct InstallCacheJson {
     pub dirty: Vec<String>,
     /// Binaries available mapping to the package that provides it
     pub bins: BTreeMap<String, String>,
+    /// Binaries mapping to the provider selected for it
+    #[serde(default)]
+    pub providers: BTreeMap<String, String>,
+    /// List of packages held at the installed version
+    #[serde(default)]
+    pub held: Vec<String>,
+    /// Packages mapping to the resources they used
+    #[serde(default)]
+    pub resources: BTreeMap<String, PackageResources>,
+    /// Installed packages mapping to information about the installation
+    #[serde(default)]
+    pub info: BTreeMap<String, InstallInfo>,
 }
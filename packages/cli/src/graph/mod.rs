@@ -1,10 +1,12 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use cu::pre::*;
 use enum_map::EnumMap;
 use enumset::EnumSet;
 use registry::{BinId, PkgId};
 
 mod install_cache;
-pub use install_cache::InstallCache;
+pub use install_cache::{InstallCache, InstallInfo, InstallReason};
 
 pub fn parse_pkgs(idents: &[String]) -> cu::Result<EnumSet<PkgId>> {
     let mut pkgs = EnumSet::new();
@@ -15,6 +17,29 @@ pub fn parse_pkgs(idents: &[String]) -> cu::Result<EnumSet<PkgId>> {
     Ok(pkgs)
 }
 
+/// Parse `BIN=PKG` pairs that select the package to provide a binary
+pub fn parse_providers(pairs: &[String]) -> cu::Result<EnumMap<BinId, Option<PkgId>>> {
+    let mut providers = EnumMap::default();
+    for pair in pairs {
+        let Some((bin, pkg)) = pair.split_once('=') else {
+            cu::bail!("invalid provider '{pair}', expected format: BIN=PKG");
+        };
+        let (bin_id, pkg_id) = parse_provider(bin.trim(), pkg.trim())?;
+        providers[bin_id] = Some(pkg_id);
+    }
+    Ok(providers)
+}
+
+/// Parse a binary and the package to provide it
+pub fn parse_provider(bin: &str, pkg: &str) -> cu::Result<(BinId, PkgId)> {
+    let bin_id = cu::check!(BinId::from_str(bin), "cannot find binary '{bin}'")?;
+    let pkg_id = cu::check!(PkgId::from_str(pkg), "cannot find package '{pkg}'")?;
+    if !bin_id.providers().contains(pkg_id) {
+        cu::bail!("package '{pkg_id}' does not provide binary '{bin_id}'");
+    }
+    Ok((bin_id, pkg_id))
+}
+
 pub fn build_remove_graph(
     pkgs: EnumSet<PkgId>,
     installed: &InstallCache,
@@ -52,6 +77,10 @@ pub fn build_remove_graph(
                 .map(|x| x.to_string())
                 .collect::<Vec<_>>()
                 .join(", ");
+            cu::hint!("use `shaft why <package>` to see what depends on a package");
+            cu::hint!(
+                "use `shaft remove --cascade` to also remove the packages that depend on them"
+            );
             cu::bail!(
                 "cannot remove the following packages because other packages depend on them: [ {pkgs_string} ]"
             );
@@ -66,6 +95,17 @@ pub fn build_sync_graph(
     installed: &InstallCache,
     provider_selection: &mut EnumMap<BinId, Option<PkgId>>,
 ) -> cu::Result<Vec<PkgId>> {
+    let levels = build_sync_levels(pkgs, installed, provider_selection)?;
+    Ok(levels.into_iter().flatten().collect())
+}
+
+/// Build the sync graph as levels. Packages in the same level
+/// do not depend on each other, and only depend on packages in previous levels
+pub fn build_sync_levels(
+    pkgs: EnumSet<PkgId>,
+    installed: &InstallCache,
+    provider_selection: &mut EnumMap<BinId, Option<PkgId>>,
+) -> cu::Result<Vec<EnumSet<PkgId>>> {
     cu::debug!("building sync graph for {pkgs}");
     let mut sync_pkgs = EnumSet::new();
     for pkg_id in pkgs {
@@ -95,8 +135,7 @@ pub fn build_sync_graph(
         "there are conflicts in new package(s) to install"
     )?;
 
-    let graph = resolve_sync_order(sync_pkgs, provider_selection)?;
-    Ok(graph)
+    resolve_sync_levels(sync_pkgs, provider_selection)
 }
 
 /// Resolve packages that should be added to sync_pkgs for config
@@ -129,16 +168,16 @@ pub fn resolve_config_pkgs(
 }
 
 #[cu::context("failed to determine sync order")]
-pub fn resolve_sync_order(
+pub fn resolve_sync_levels(
     pkgs: EnumSet<PkgId>,
     bin_providers: &EnumMap<BinId, Option<PkgId>>,
-) -> cu::Result<Vec<PkgId>> {
+) -> cu::Result<Vec<EnumSet<PkgId>>> {
     cu::debug!("resolving sync order: {pkgs}, bin_providers={bin_providers:?}");
     let mut remaining = pkgs;
-    let mut out = Vec::with_capacity(pkgs.len() + 1);
+    let mut out = Vec::new();
     // always sync core-pseudo first
     remaining.remove(PkgId::Core);
-    out.push(PkgId::Core);
+    out.push(PkgId::Core.into());
     while !remaining.is_empty() {
         let mut next_to_add = EnumSet::new();
         'outer: for pkg_id in remaining {
@@ -178,7 +217,7 @@ pub fn resolve_sync_order(
                 "the order of the remaining packages cannot be determined: [ {pkgs_string} ]"
             );
         }
-        out.extend(next_to_add);
+        out.push(next_to_add);
         remaining.remove_all(next_to_add);
     }
     Ok(out)
@@ -213,6 +252,82 @@ pub fn collect_dependencies(
     Ok(())
 }
 
+/// Reason a package depends on another package
+pub enum Dependency {
+    /// Depends on a binary provided by the package
+    Binary(BinId),
+    /// Depends on the config of the package
+    Config,
+}
+
+/// Find installed packages that directly depend on the package
+pub fn find_dependents(pkg: PkgId, installed: &InstallCache) -> Vec<(PkgId, Dependency)> {
+    let mut out = vec![];
+    for other in installed.pkgs {
+        if other == pkg {
+            continue;
+        }
+        let package = other.package();
+        for bin_id in package.binary_dependencies() {
+            if resolve_provider(bin_id, installed) == Some(pkg) {
+                out.push((other, Dependency::Binary(bin_id)));
+            }
+        }
+        if package.config_dependencies().contains(pkg) {
+            out.push((other, Dependency::Config));
+        }
+    }
+    out
+}
+
+/// Find installed packages that depend on binaries of the packages, directly or indirectly.
+///
+/// The packages themselves are not included
+pub fn find_binary_dependents_recursive(
+    pkgs: EnumSet<PkgId>,
+    installed: &InstallCache,
+) -> EnumSet<PkgId> {
+    let mut out = EnumSet::new();
+    let mut queue = pkgs;
+    while let Some(pkg) = queue.iter().next() {
+        queue.remove(pkg);
+        for (other, dependency) in find_dependents(pkg, installed) {
+            if !matches!(dependency, Dependency::Binary(_)) {
+                continue;
+            }
+            if pkgs.contains(other) || out.contains(other) {
+                continue;
+            }
+            out.insert(other);
+            queue.insert(other);
+        }
+    }
+    out
+}
+
+/// Get the provider of the binary, using the current or saved provider selection
+pub fn resolve_provider(bin_id: BinId, installed: &InstallCache) -> Option<PkgId> {
+    if let Some(pkg_id) = installed.bins[bin_id] {
+        return Some(pkg_id);
+    }
+    if let Some(pkg_id) = installed.providers[bin_id] {
+        return Some(pkg_id);
+    }
+    let providers = bin_id.providers();
+    if providers.len() == 1 {
+        return providers.into_iter().next();
+    }
+    None
+}
+
+/// Whether to error instead of prompting when a provider needs to be selected
+static NO_PROVIDER_PROMPT: AtomicBool = AtomicBool::new(false);
+
+/// Error instead of prompting for providers, for commands that must not wait for input
+pub fn disable_provider_prompt() {
+    NO_PROVIDER_PROMPT.store(true, Ordering::Relaxed);
+}
+
 #[cu::context("failed to select provider for binary '{bin_id}'")]
 pub fn select_provider(
     provider_selection: &mut EnumMap<BinId, Option<PkgId>>,
@@ -236,6 +351,15 @@ pub fn select_provider(
         cu::bail!("no provider found for binary '{bin_id}'");
     }
 
+    // use the provider selected previously
+    if let Some(pkg_id) = installed.providers[bin_id] {
+        if providers.contains(pkg_id) {
+            cu::debug!("using saved provider for '{bin_id}': '{pkg_id}'");
+            provider_selection[bin_id] = Some(pkg_id);
+            return Ok(pkg_id);
+        }
+    }
+
     // if there is only one provider for the binary, use that pkg
     if providers.len() == 1 {
         let pkg_id = providers.into_iter().next().unwrap();
@@ -244,6 +368,17 @@ pub fn select_provider(
         return Ok(pkg_id);
     }
 
+    if NO_PROVIDER_PROMPT.load(Ordering::Relaxed) {
+        let pkgs_string = providers
+            .iter()
+            .map(|x| x.to_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        cu::bail!(
+            "provider for '{bin_id}' must be given with `--provider {bin_id}=<package>`, one of: [ {pkgs_string} ]"
+        );
+    }
+
     // prompt for a provider
     let mut prompt = String::new();
     let _ = writeln!(prompt, "please select a provider for binary '{bin_id}':");
@@ -262,6 +397,10 @@ pub fn select_provider(
             width = pkg_width
         );
     }
+    let _ = writeln!(
+        prompt,
+        "(use `--provider {bin_id}=<package>` to select without prompting)"
+    );
     let _ = write!(prompt, "--- enter a number:");
     let mut pkg_id = PkgId::Core;
     cu::prompt(prompt)
@@ -289,3 +428,67 @@ pub fn select_provider(
     cu::debug!("user selected provider for '{bin_id}': '{pkg_id}'");
     Ok(pkg_id)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn levels(
+        pkgs: EnumSet<PkgId>,
+        installed: &InstallCache,
+        provider_selection: &mut EnumMap<BinId, Option<PkgId>>,
+    ) -> Vec<EnumSet<PkgId>> {
+        build_sync_levels(pkgs, installed, provider_selection).unwrap()
+    }
+
+    #[test]
+    fn test_sync_levels_dependency_order() {
+        let installed = InstallCache::default();
+        let mut selection = EnumMap::default();
+        let levels = levels(PkgId::Python.into(), &installed, &mut selection);
+        assert_eq!(
+            levels,
+            vec![
+                EnumSet::only(PkgId::Core),
+                EnumSet::only(PkgId::CargoBinstall),
+                EnumSet::only(PkgId::Python),
+            ]
+        );
+        assert_eq!(selection[BinId::CargoBinstall], Some(PkgId::CargoBinstall));
+    }
+
+    #[test]
+    fn test_sync_levels_installed_dependency() {
+        let mut installed = InstallCache::default();
+        installed.add(PkgId::SystemGit).unwrap();
+        let mut selection = EnumMap::default();
+        // the installed provider is used without prompting, and synced first
+        let levels = levels(PkgId::GitCfg.into(), &installed, &mut selection);
+        assert_eq!(
+            levels,
+            vec![
+                EnumSet::only(PkgId::Core),
+                EnumSet::only(PkgId::SystemGit),
+                EnumSet::only(PkgId::GitCfg),
+            ]
+        );
+        assert_eq!(selection[BinId::Git], Some(PkgId::SystemGit));
+    }
+
+    #[test]
+    fn test_sync_levels_provider_override() {
+        let installed = InstallCache::default();
+        let mut selection = EnumMap::default();
+        selection[BinId::Git] = Some(PkgId::SystemGit);
+        let levels = levels(PkgId::GitCfg.into(), &installed, &mut selection);
+        assert_eq!(
+            levels,
+            vec![
+                EnumSet::only(PkgId::Core),
+                EnumSet::only(PkgId::SystemGit),
+                EnumSet::only(PkgId::GitCfg),
+            ]
+        );
+        assert_eq!(selection[BinId::Git], Some(PkgId::SystemGit));
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use corelib::{hmgr, opfs};
+use cu::pre::*;
+use registry::{Context, PkgId, Stage};
+
+/// Max number of records to keep in the history
+const MAX_RECORDS: usize = 200;
+
+/// The record of the current run
+static CURRENT: Mutex<Option<Recorder>> = Mutex::new(None);
+
+struct Recorder {
+    start: Instant,
+    record: HistoryRecord,
+    /// Stage currently running for each package, and when it started.
+    /// Multiple packages may be running at the same time
+    stages: Vec<(PkgId, Stage, Instant)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HistoryRecord {
+    pub id: u64,
+    /// Seconds since UNIX epoch when the command started
+    pub timestamp: u64,
+    /// Command line of the run
+    pub command: String,
+    /// Packages in the order they are processed
+    #[serde(default)]
+    pub graph: Vec<String>,
+    #[serde(default)]
+    pub packages: Vec<PackageRecord>,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Error chain, if the command failed
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackageRecord {
+    pub name: String,
+    /// What is done to the package (for example, sync type or remove)
+    pub action: String,
+    /// Time spent in each stage
+    #[serde(default)]
+    pub stages: Vec<(String, u64)>,
+}
+
+/// Run the command and record it in the history
+pub fn record(f: impl FnOnce() -> cu::Result<()>) -> cu::Result<()> {
+    begin();
+    let result = f();
+    finish(&result);
+    result
+}
+
+fn begin() {
+    let command = std::env::args().collect::<Vec<_>>().join(" ");
+    let record = HistoryRecord {
+        id: 0,
+        timestamp: opfs::now_unix(),
+        command,
+        graph: vec![],
+        packages: vec![],
+        duration_ms: 0,
+        success: false,
+        errors: vec![],
+    };
+    *lock() = Some(Recorder {
+        start: Instant::now(),
+        record,
+        stages: vec![],
+    });
+}
+
+fn finish(result: &cu::Result<()>) {
+    let Some(mut recorder) = lock().take() else {
+        return;
+    };
+    let running = recorder
+        .stages
+        .iter()
+        .map(|(x, _, _)| *x)
+        .collect::<Vec<_>>();
+    for pkg in running {
+        recorder.end_stage(pkg);
+    }
+    let mut record = recorder.record;
+    record.duration_ms = recorder.start.elapsed().as_millis() as u64;
+    match result {
+        Ok(()) => record.success = true,
+        Err(e) => record.errors = e.chain().map(|x| x.to_string()).collect(),
+    }
+    if let Err(e) = save(record) {
+        cu::warn!("failed to save history: {e:?}");
+    }
+}
+
+/// Record the packages to process, in order
+pub fn set_graph(graph: &[PkgId]) {
+    with_recorder(|recorder| {
+        recorder
+            .record
+            .graph
+            .extend(graph.iter().map(|x| x.to_string()));
+    });
+}
+
+/// Record what is done to the package
+pub fn set_action(pkg: PkgId, action: impl Display) {
+    let action = action.to_string();
+    with_recorder(|recorder| {
+        recorder.package(pkg).action = action;
+    });
+}
+
+/// Set the stage of the context, and record the time spent in the previous stage
+pub fn set_stage(ctx: &Context, stage: Stage) {
+    ctx.stage.set(stage);
+    with_recorder(|recorder| {
+        recorder.end_stage(ctx.pkg);
+        recorder.stages.push((ctx.pkg, stage, Instant::now()));
+    });
+}
+
+impl Recorder {
+    fn package(&mut self, pkg: PkgId) -> &mut PackageRecord {
+        let name = pkg.to_str();
+        let index = match self.record.packages.iter().position(|x| x.name == name) {
+            Some(i) => i,
+            None => {
+                self.record.packages.push(PackageRecord {
+                    name: name.to_string(),
+                    action: String::new(),
+                    stages: vec![],
+                });
+                self.record.packages.len() - 1
+            }
+        };
+        &mut self.record.packages[index]
+    }
+    fn end_stage(&mut self, pkg: PkgId) {
+        let Some(index) = self.stages.iter().position(|(x, _, _)| *x == pkg) else {
+            return;
+        };
+        let (_, stage, start) = self.stages.remove(index);
+        let elapsed = start.elapsed().as_millis() as u64;
+        let stage = stage.to_string();
+        let package = self.package(pkg);
+        match package.stages.iter_mut().find(|(x, _)| *x == stage) {
+            Some((_, ms)) => *ms += elapsed,
+            None => package.stages.push((stage, elapsed)),
+        }
+    }
+}
+
+fn with_recorder(f: impl FnOnce(&mut Recorder)) {
+    if let Some(recorder) = lock().as_mut() {
+        f(recorder)
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Recorder>> {
+    // the recorder is never left in an inconsistent state
+    CURRENT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cu::context("failed to save history record")]
+fn save(mut record: HistoryRecord) -> cu::Result<()> {
+    cu::fs::make_dir(hmgr::paths::history_root())?;
+    let ids = list_ids()?;
+    record.id = ids.last().copied().unwrap_or_default() + 1;
+    cu::fs::write_json_pretty(
+        hmgr::paths::history_file(format!("{}.json", record.id)),
+        &record,
+    )?;
+    // remove the oldest records
+    let len = ids.len() + 1;
+    if len > MAX_RECORDS {
+        for id in ids.iter().take(len - MAX_RECORDS) {
+            cu::fs::remove(hmgr::paths::history_file(format!("{id}.json")))?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the ids of the records in the history, oldest first
+pub fn list_ids() -> cu::Result<Vec<u64>> {
+    let root = hmgr::paths::history_root();
+    if !root.exists() {
+        return Ok(vec![]);
+    }
+    let mut ids = vec![];
+    for entry in cu::fs::read_dir(&root)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|x| x.strip_suffix(".json"))
+            .and_then(|x| x.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        ids.push(id);
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Load a record from the history
+#[cu::context("failed to load history record {id}")]
+pub fn load(id: u64) -> cu::Result<HistoryRecord> {
+    let content = cu::fs::read_string(hmgr::paths::history_file(format!("{id}.json")))?;
+    json::parse(&content)
+}
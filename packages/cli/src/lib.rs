@@ -3,4 +3,6 @@ pub use api::CliApi;
 mod cmds;
 mod config;
 mod graph;
+mod history;
 mod init;
+mod manifest;
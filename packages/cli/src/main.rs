@@ -1,5 +1,10 @@
 use shaft_cli::CliApi;
 #[cu::cli(preprocess = CliApi::preprocess)]
 fn main(cli: CliApi) -> cu::Result<()> {
-    cli.run()
+    // exit after run() returns, so the home lock is released
+    let code = cli.run()?;
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
 }
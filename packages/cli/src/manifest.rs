@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use cu::pre::*;
+use enum_map::EnumMap;
+use enumset::EnumSet;
+use registry::{BinId, PkgId};
+
+/// Latest version of the manifest format
+const MANIFEST_VERSION: u32 = 1;
+
+/// Declarative description of what should be installed on a machine
+///
+/// ```toml
+/// version = 1
+/// packages = ["git", "nvim"]
+///
+/// [providers]
+/// python = "system-python"
+///
+/// [config.nvim]
+/// nvim-tree-git = true
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ManifestToml {
+    /// Version of the manifest format
+    version: u32,
+    /// Packages to install
+    #[serde(default)]
+    packages: Vec<String>,
+    /// Binary name to the package that should provide it
+    #[serde(default)]
+    providers: BTreeMap<String, String>,
+    /// Package name to the config values to set for the package
+    #[serde(default)]
+    config: BTreeMap<String, toml::Table>,
+}
+
+/// Manifest with package and binary names resolved
+pub struct Manifest {
+    /// Packages to install
+    pub pkgs: EnumSet<PkgId>,
+    /// Providers to use for binaries
+    pub providers: EnumMap<BinId, Option<PkgId>>,
+    /// Config values to set for packages
+    pub config_overrides: EnumMap<PkgId, Option<toml::Table>>,
+}
+
+impl Manifest {
+    #[cu::context("failed to load manifest '{}'", path.display())]
+    pub fn load(path: &Path) -> cu::Result<Self> {
+        let content = cu::fs::read_string(path)?;
+        let manifest = toml::parse::<ManifestToml>(&content)?;
+        if manifest.version > MANIFEST_VERSION {
+            cu::bail!(
+                "manifest version {} is not supported (latest is {MANIFEST_VERSION}), please upgrade shaft",
+                manifest.version
+            );
+        }
+        let pkgs = crate::graph::parse_pkgs(&manifest.packages)?;
+        let mut providers = EnumMap::default();
+        for (bin, pkg) in &manifest.providers {
+            let (bin_id, pkg_id) = crate::graph::parse_provider(bin, pkg)?;
+            providers[bin_id] = Some(pkg_id);
+        }
+        let mut config_overrides = EnumMap::default();
+        for (pkg, overrides) in manifest.config {
+            let pkg_id = cu::check!(PkgId::from_str(&pkg), "cannot find package '{pkg}'")?;
+            config_overrides[pkg_id] = Some(overrides);
+        }
+        Ok(Self {
+            pkgs,
+            providers,
+            config_overrides,
+        })
+    }
+}
@@ -1,11 +1,12 @@
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use cu::pre::*;
 
-use crate::internal;
+use crate::{hmgr, internal};
 
-internal::main_thread_singleton! {
+internal::serialized_singleton! {
     const cargo = Cargo::new();
 }
 
@@ -91,6 +92,7 @@ pub fn install_git_commit(
         .add(cu::args![
             "install", package, "--git", git, "--rev", rev, "--locked"
         ])
+        .args(offline_args())
         .preset(
             cu::pio::cargo(format!("cargo install '{package}'"))
                 .configure_spinner(|builder| builder.keep(true).parent(bar.cloned())),
@@ -110,6 +112,7 @@ pub fn install(package: &str, bar: Option<&Arc<cu::ProgressBar>>) -> cu::Result<
     let (child, bar) = cu::which("cargo")?
         .command()
         .add(cu::args!["install", package, "--locked"])
+        .args(offline_args())
         .preset(
             cu::pio::cargo(format!("cargo install '{package}'"))
                 .configure_spinner(|builder| builder.keep(true).parent(bar.cloned())),
@@ -125,6 +128,11 @@ pub fn install(package: &str, bar: Option<&Arc<cu::ProgressBar>>) -> cu::Result<
 /// Install a package using `cargo binstall` (with fallback)
 #[cu::context("failed to install '{package}' with cargo-binstall")]
 pub fn binstall(package: &str, bar: Option<&Arc<cu::ProgressBar>>) -> cu::Result<()> {
+    if hmgr::is_offline() {
+        // cargo-binstall always queries the registry, build from the local cargo cache instead
+        cu::debug!("cargo: offline mode, using cargo install instead of cargo-binstall");
+        return install(package, bar);
+    }
     let mut state = cargo::instance()?;
     let (child, bar) = cu::which("cargo-binstall")?
         .command()
@@ -197,3 +205,34 @@ pub fn uninstall(package: &str) -> cu::Result<()> {
     cu::info!("uninstalled '{package}' with cargo");
     Ok(())
 }
+
+/// Check if any version of the crate is in the cargo registry cache,
+/// so it can be installed in offline mode.
+///
+/// Only the crate itself is checked. Its dependencies can still be missing
+/// from the cache, in which case `cargo install --offline` fails
+pub fn is_cached(package: &str) -> bool {
+    let cargo_home = match std::env::var_os("CARGO_HOME") {
+        Some(x) => PathBuf::from(x),
+        None => match std::env::home_dir() {
+            Some(x) => x.join(".cargo"),
+            None => return false,
+        },
+    };
+    let Ok(entries) = std::fs::read_dir(cargo_home.join("registry").join("cache")) else {
+        return false;
+    };
+    // one directory for each registry index
+    entries
+        .filter_map(|x| x.ok())
+        .any(|entry| super::has_cached_file(&entry.path(), package))
+}
+
+/// Extra arguments for cargo to not use the network in offline mode
+fn offline_args() -> &'static [&'static str] {
+    if hmgr::is_offline() {
+        &["--offline"]
+    } else {
+        &[]
+    }
+}
@@ -3,3 +3,21 @@ pub mod cargo;
 pub mod pacman;
 #[cfg(windows)]
 pub mod winget;
+
+/// Check if the directory has a file of any version of the package,
+/// named `<package>-<version>...`
+fn has_cached_file(dir: &std::path::Path, package: &str) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    entries.filter_map(|x| x.ok()).any(|entry| {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            return false;
+        };
+        // the version must follow, so `git` does not match `git-lfs-...`
+        name.strip_prefix(package)
+            .and_then(|x| x.strip_prefix('-'))
+            .is_some_and(|x| x.starts_with(|c: char| c.is_ascii_digit()))
+    })
+}
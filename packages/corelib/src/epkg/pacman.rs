@@ -5,9 +5,9 @@ use std::time::{Duration, Instant};
 
 use cu::pre::*;
 
-use crate::{internal, opfs};
+use crate::{hmgr, internal, opfs};
 
-internal::main_thread_singleton! {
+internal::serialized_singleton! {
     const pacman = Pacman::new();
 }
 
@@ -53,6 +53,12 @@ pub fn installed_version(package_name: &str) -> cu::Result<Option<String>> {
     Ok(version.cloned())
 }
 
+/// Check if any version of the package is in the pacman package cache,
+/// so it can be installed in offline mode
+pub fn is_cached(package_name: &str) -> bool {
+    super::has_cached_file(Path::new("/var/cache/pacman/pkg"), package_name)
+}
+
 #[cu::context("failed to install '{package_name}' with pacman")]
 pub fn install(package_name: &str, bar: Option<&Arc<cu::ProgressBar>>) -> cu::Result<()> {
     let reason = format!("installing {package_name}");
@@ -93,6 +99,10 @@ pub fn install_file(path: &Path, bar: Option<&Arc<cu::ProgressBar>>) -> cu::Resu
 
 #[cu::context("failed to sync pacman database")]
 fn sync_database(bar: Option<&Arc<cu::ProgressBar>>, reason: &str) -> cu::Result<()> {
+    if hmgr::is_offline() {
+        cu::debug!("pacman: offline mode, using the local database");
+        return Ok(());
+    }
     let mut state = pacman::instance()?;
     if state
         .db_synced_time
@@ -122,6 +122,47 @@ where
             "failed to parse typed config object"
         )
     }
+    /// Load the configuration file, then apply the overrides on top of it.
+    ///
+    /// The file is rewritten if the overrides change any value
+    #[inline(always)]
+    pub fn load_with_overrides(
+        self,
+        path: impl AsRef<Path>,
+        overrides: &toml::Table,
+    ) -> cu::Result<T> {
+        self.load_with_overrides_impl(path.as_ref(), overrides)
+    }
+    fn load_with_overrides_impl(self, path: &Path, overrides: &toml::Table) -> cu::Result<T> {
+        // creates or migrates the file if needed
+        self.load_impl(path)?;
+        let file_content = cu::fs::read_string(path)?;
+        let mut object = cu::check!(
+            toml::parse::<toml::Table>(&file_content),
+            "failed to parse config file as TOML: '{}'",
+            path.display()
+        )?;
+        if !config::merge_config_values(&mut object, overrides) {
+            cu::debug!("config overrides already applied: '{}'", path.display());
+            return cu::check!(
+                toml::parse::<T>(&file_content),
+                "failed to parse typed config object"
+            );
+        }
+        cu::info!("applying config overrides to '{}'", path.display());
+        let template = toml::parse::<ConfigTemplate>(self.template_str)?;
+        let content = config::serialize_config(&template, self.current_version(), &mut object);
+        cu::fs::write(path, &content)?;
+
+        let (unused_count, unused_repr) = config::serialize_leaf_key_values(&object);
+        if unused_count > 0 {
+            cu::warn!("there were {unused_count} unused config keys:\n{unused_repr}");
+        }
+        cu::check!(
+            toml::parse::<T>(&content),
+            "failed to parse typed config object"
+        )
+    }
     pub const fn current_version(self) -> usize {
         self.migration_scripts.len()
     }
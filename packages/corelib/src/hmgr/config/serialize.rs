@@ -32,6 +32,27 @@ pub fn serialize_leaf_key_values(value: &toml::Table) -> (usize, String) {
 
     (count, out)
 }
+/// Recursively merge `overrides` into `value`. Tables are merged key by key,
+/// all other values are replaced.
+///
+/// Return true if anything in `value` changed
+pub fn merge_config_values(value: &mut toml::Table, overrides: &toml::Table) -> bool {
+    let mut changed = false;
+    for (key, override_value) in overrides {
+        if let (Some(toml::Value::Table(current)), toml::Value::Table(override_table)) =
+            (value.get_mut(key), override_value)
+        {
+            changed |= merge_config_values(current, override_table);
+            continue;
+        }
+        if value.get(key) != Some(override_value) {
+            value.insert(key.to_string(), override_value.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
 /// Serialize a config template into the default configuration file
 pub fn serialize_config_template(template: &ConfigTemplate, version: usize) -> String {
     serialize_config(template, version, &mut Default::default())
@@ -334,6 +355,35 @@ barb.foo = "hello"
         assert_eq!(remaining, "this.is.unused.key = \"hehehe\"\n");
     }
 
+    #[test]
+    fn merge_values() {
+        let mut value = toml! {
+            a = 1
+            b.c = "hello"
+            b.d = [1, 2]
+        };
+        let overrides = toml! {
+            b.c = "hello"
+        };
+        assert!(!merge_config_values(&mut value, &overrides));
+
+        let overrides = toml! {
+            a = 2
+            b.d = [3]
+            e = { f = true }
+        };
+        assert!(merge_config_values(&mut value, &overrides));
+        assert_eq!(
+            value,
+            toml! {
+                a = 2
+                b.c = "hello"
+                b.d = [3]
+                e = { f = true }
+            }
+        );
+    }
+
     #[test]
     pub fn pretty_value() {
         let value = toml::Value::Table(toml! {
@@ -1,56 +1,53 @@
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, LazyLock};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 use cu::pre::*;
 use reqwest::Client;
-use reqwest::header::CONTENT_LENGTH;
-
-use crate::{hmgr, opfs};
-
-static CLIENT: LazyLock<Result<Client, String>> = LazyLock::new(|| {
-    Client::builder()
-        .gzip(true)
-        .https_only(true)
-        .build()
-        .map_err(|x| format!("{x}"))
-});
-
-fn client() -> cu::Result<&'static Client> {
-    let client: &Result<Client, String> = &CLIENT;
-    match client {
-        Ok(c) => Ok(c),
-        Err(e) => {
-            cu::bail!("error initializing https client: {e}")
-        }
-    }
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+
+use crate::hmgr;
+use crate::hmgr::download_index;
+use crate::hmgr::integrity::{Digest, Integrity};
+use crate::hmgr::network::{self, Source};
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Set if the network should not be used. In offline mode, downloads
+/// only use the download cache, and package managers are told to stay offline
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Check if the network should not be used
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
 }
 
 /// Download URL to a temporary location, return the path to the downloaded file.
 ///
-/// The SHA256 checksum is used to verify integrity.
+/// The [`Integrity`] is used to verify the file before it's returned.
+/// A SHA256 checksum (`&str`) can be passed directly.
 ///
-/// The result is cached across multiple runs
+/// The result is cached across multiple runs. Signatures are checked when
+/// the file is put in the cache, and digests are checked every time
 #[inline(always)]
 pub fn download_file(
     identifier: impl AsRef<Path>,
     url: impl AsRef<str>,
-    sha256_checksum: impl AsRef<str>,
+    integrity: impl Into<Integrity>,
     bar: Option<Arc<cu::ProgressBar>>,
 ) -> cu::Result<PathBuf> {
-    download_file_impl(
-        identifier.as_ref(),
-        url.as_ref(),
-        sha256_checksum.as_ref(),
-        bar,
-    )
+    download_file_impl(identifier.as_ref(), url.as_ref(), &integrity.into(), bar)
 }
 #[cu::context("failed to download {} from {}", identifier.display(), url)]
 fn download_file_impl(
     identifier: &Path,
     url: &str,
-    sha256_checksum: &str,
+    integrity: &Integrity,
     bar: Option<Arc<cu::ProgressBar>>,
 ) -> cu::Result<PathBuf> {
     cu::debug!(
@@ -59,24 +56,43 @@ fn download_file_impl(
         url
     );
     let target_path = hmgr::paths::download(identifier, url);
-    let sha256_checksum = sha256_checksum.to_ascii_lowercase();
+    hmgr::PackageResources::record_download(&target_path);
+    let digest = integrity.primary();
     if target_path.exists() {
         let bar = cu::progress(format!("checking cached {}", identifier.display()))
             .parent(bar.clone())
             .spawn();
-        let actual_checksum = opfs::file_sha256(&target_path, Some(bar.clone()))?;
-        if sha256_checksum == actual_checksum {
-            cu::progress!(bar, "disk cache");
-            bar.done();
-            cu::debug!(
-                "got file from cache: {} ({})",
-                identifier.display(),
-                sha256_checksum
-            );
-            return Ok(target_path);
+        match integrity.verify_digests(&target_path, Some(bar.clone())) {
+            Ok(()) => {
+                cu::progress!(bar, "disk cache");
+                bar.done();
+                cu::debug!("got file from cache: {} ({digest})", identifier.display());
+                record_index(&target_path, url, digest);
+                return Ok(target_path);
+            }
+            Err(e) => {
+                cu::debug!("cached file is invalid: {e:?}");
+            }
         }
         bar.done();
     }
+    let original_url = url;
+    let url = match network::resolve_source(url)? {
+        Source::Local(path) => {
+            copy_verified(&path, &target_path, integrity, bar)?;
+            cu::info!("copied {} from {}", identifier.display(), path.display());
+            record_index(&target_path, original_url, digest);
+            return Ok(target_path);
+        }
+        Source::Remote(url) => url,
+    };
+    if is_offline() {
+        cu::bail!(
+            "{} is not in the download cache, and cannot be downloaded in offline mode",
+            identifier.display()
+        );
+    }
+    let url = url.as_str();
     let bar = cu::progress(format!("{}", identifier.display()))
         .parent(bar)
         .spawn();
@@ -97,26 +113,20 @@ fn download_file_impl(
                 )
             }
         }
-        let path = target_path.clone();
-        let url = url.to_string();
-        let bar_ = bar.clone();
-        let result = cu::co::run(async move { do_download_file(path, url, Some(bar_)).await });
+        // the partial download is kept to resume from in the next retry
+        let result = download_verified(network::client()?, url, &target_path, integrity, &bar);
         if let Err(e) = result {
+            if e.downcast_ref::<FatalDownloadError>().is_some() {
+                bar.done();
+                return Err(e);
+            }
             cu::warn!("failed to download {}: {:?}", identifier.display(), e);
             continue;
         }
-        let actual_checksum = match opfs::file_sha256(&target_path, Some(bar.clone())) {
-            Err(e) => {
-                cu::warn!("failed to hash {}: {:?}", identifier.display(), e);
-                continue;
-            }
-            Ok(x) => x,
-        };
-        if sha256_checksum == actual_checksum {
-            cu::info!("downloaded {} ({})", identifier.display(), sha256_checksum);
-            bar.done();
-            return Ok(target_path);
-        }
+        cu::info!("downloaded {} ({digest})", identifier.display());
+        bar.done();
+        record_index(&target_path, original_url, digest);
+        return Ok(target_path);
     }
     cu::bail!(
         "failed to download {}, see error messages above",
@@ -124,34 +134,513 @@ fn download_file_impl(
     );
 }
 
-async fn do_download_file(
-    path: PathBuf,
-    url: String,
+/// Record the file in the download index, with the URL before rewriting
+fn record_index(target_path: &Path, url: &str, digest: &Digest) {
+    let Some(name) = target_path.file_name().and_then(|x| x.to_str()) else {
+        return;
+    };
+    let size = target_path.metadata().map(|x| x.len()).unwrap_or_default();
+    download_index::record_download(name, url, digest, size);
+}
+
+/// Copy a local file to the target path, if it passes the integrity check
+fn copy_verified(
+    path: &Path,
+    target_path: &Path,
+    integrity: &Integrity,
     bar: Option<Arc<cu::ProgressBar>>,
 ) -> cu::Result<()> {
-    let bar = cu::progress("downloaded")
-        .total_bytes(0)
+    integrity.verify_digests(path, bar)?;
+    integrity.verify_signature(path)?;
+    cu::fs::copy(path, target_path)?;
+    Ok(())
+}
+
+/// Download error that retrying doesn't fix, like a missing file or a checksum mismatch
+#[derive(Debug)]
+struct FatalDownloadError(String);
+impl std::fmt::Display for FatalDownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl std::error::Error for FatalDownloadError {}
+
+/// Check if the request can succeed if sent again, when the server responded with the status
+fn is_retryable(status: StatusCode) -> bool {
+    !status.is_client_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Files at least this large are downloaded in parallel chunks, if the server supports it
+const CHUNKED_MIN_SIZE: u64 = 64 * 1024 * 1024;
+/// Number of chunks to download in parallel
+const CHUNK_COUNT: u64 = 4;
+
+/// Download the URL to the target path through a `.part` file next to it,
+/// which is resumed from if it exists. The file is only moved to the target path
+/// if it passes the integrity check.
+///
+/// Errors with [`FatalDownloadError`] if retrying won't help
+fn download_verified(
+    client: &Client,
+    url: &str,
+    target_path: &Path,
+    integrity: &Integrity,
+    bar: &Arc<cu::ProgressBar>,
+) -> cu::Result<()> {
+    let part_path = with_suffix(target_path, ".part");
+    let chunked_size = if part_path.exists() {
+        // resume the download that is not chunked
+        None
+    } else {
+        let (length, accept_ranges) = probe(client, url);
+        length.filter(|x| accept_ranges && *x >= CHUNKED_MIN_SIZE)
+    };
+    let bytes_bar = cu::progress("downloaded")
+        .total_bytes(chunked_size.unwrap_or_default())
         .eta(true)
         .percentage(true)
         .keep(true)
-        .parent(bar)
+        .parent(Some(bar.clone()))
         .spawn();
-    cu::progress!(bar, "{url}");
-    let mut response = cu::check!(client()?.get(url).send().await, "failed to send request")?;
+    cu::progress!(bytes_bar, "{url}");
+    match chunked_size {
+        Some(length) => download_chunked(client, url, &part_path, length, &bytes_bar)?,
+        None => {
+            let (client, url, path) = (client.clone(), url.to_string(), part_path.clone());
+            let bar = bytes_bar.clone();
+            cu::co::run(async move { download_range(client, url, path, 0, None, bar).await })?;
+        }
+    }
+    bytes_bar.done();
+    let result = integrity
+        .verify_digests(&part_path, Some(bar.clone()))
+        .and_then(|_| integrity.verify_signature(&part_path));
+    if let Err(e) = result {
+        // the content is wrong, so it can't be resumed from
+        cu::fs::remove(&part_path)?;
+        return Err(FatalDownloadError(format!("{e:#}")).into());
+    }
+    cu::fs::rename(&part_path, target_path)?;
+    Ok(())
+}
+
+/// Get the length of the file at the URL, and if range requests are supported
+fn probe(client: &Client, url: &str) -> (Option<u64>, bool) {
+    let (client, url) = (client.clone(), url.to_string());
+    let result = cu::co::run(async move { client.head(url).send().await });
+    let response = match result {
+        Ok(x) if x.status().is_success() => x,
+        Ok(x) => {
+            cu::debug!("HEAD request failed with status {}", x.status());
+            return (None, false);
+        }
+        Err(e) => {
+            cu::debug!("HEAD request failed: {e}");
+            return (None, false);
+        }
+    };
+    let headers = response.headers();
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.parse::<u64>().ok());
+    let accept_ranges = headers
+        .get(ACCEPT_RANGES)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.contains("bytes"));
+    (length, accept_ranges)
+}
+
+/// Download the file in ranged chunks in parallel, then join them into the part file
+fn download_chunked(
+    client: &Client,
+    url: &str,
+    part_path: &Path,
+    length: u64,
+    bar: &Arc<cu::ProgressBar>,
+) -> cu::Result<()> {
+    let chunk_size = length.div_ceil(CHUNK_COUNT);
+    let chunks = (0..CHUNK_COUNT)
+        .map(|i| {
+            let start = i * chunk_size;
+            let end = ((i + 1) * chunk_size).min(length) - 1;
+            (with_suffix(part_path, &format!(".{i}")), start, end)
+        })
+        .filter(|(_, start, end)| start <= end)
+        .collect::<Vec<_>>();
+    cu::debug!("downloading {url} in {} chunks", chunks.len());
+    let results = std::thread::scope(|s| {
+        let handles = chunks
+            .iter()
+            .map(|(path, start, end)| {
+                let (client, url, path, bar) =
+                    (client.clone(), url.to_string(), path.clone(), bar.clone());
+                let (start, end) = (*start, *end);
+                s.spawn(move || {
+                    cu::co::run(async move {
+                        download_range(client, url, path, start, Some(end), bar).await
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|x| x.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect::<Vec<_>>()
+    });
+    for result in results {
+        result?;
+    }
+    let mut writer = cu::fs::buf_writer(part_path)?;
+    for (path, _, _) in &chunks {
+        let mut reader = cu::fs::reader(path)?;
+        std::io::copy(&mut reader, &mut writer)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    for (path, _, _) in &chunks {
+        cu::fs::remove(path)?;
+    }
+    Ok(())
+}
+
+/// Download the bytes from `start` to `end` (inclusive, or to the end of the file if `None`)
+/// and append them to the file at path, skipping the bytes already in the file
+async fn download_range(
+    client: Client,
+    url: String,
+    path: PathBuf,
+    start: u64,
+    end: Option<u64>,
+    bar: Arc<cu::ProgressBar>,
+) -> cu::Result<()> {
+    let existing = path.metadata().map(|x| x.len()).unwrap_or_default();
+    let offset = start + existing;
+    if end.is_some_and(|end| offset > end) {
+        // already downloaded
+        return Ok(());
+    }
+    let mut request = client.get(&url);
+    if offset > 0 || end.is_some() {
+        let end = end.map(|x| x.to_string()).unwrap_or_default();
+        request = request.header(RANGE, format!("bytes={offset}-{end}"));
+    }
+    let mut response = cu::check!(request.send().await, "failed to send request")?;
+    let status = response.status();
+    let append = match status {
+        StatusCode::PARTIAL_CONTENT => true,
+        StatusCode::OK if start == 0 && end.is_none() => {
+            if existing > 0 {
+                cu::debug!("server does not support range requests, restarting download");
+            }
+            false
+        }
+        StatusCode::OK => cu::bail!("server does not support range requests"),
+        StatusCode::RANGE_NOT_SATISFIABLE if end.is_none() && existing > 0 => {
+            // the part file has everything, the checksum will tell if it's correct
+            return Ok(());
+        }
+        _ if !is_retryable(status) => {
+            return Err(
+                FatalDownloadError(format!("server responded with status {status}")).into(),
+            );
+        }
+        _ => cu::bail!("server responded with status {status}"),
+    };
     let length = response
         .headers()
         .get(CONTENT_LENGTH)
         .and_then(|x| x.to_str().ok())
         .and_then(|x| x.parse::<u64>().ok());
-    if let Some(l) = length {
-        bar.set_total(l);
+    let done = if append { existing } else { 0 };
+    // the total is already set when downloading in chunks
+    if let Some(l) = length.filter(|_| end.is_none()) {
+        bar.set_total(done + l);
     }
-    let mut writer = cu::fs::buf_writer(path)?;
-
+    if done > 0 {
+        cu::progress!(bar += done as usize);
+    }
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(&path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    let mut received = 0u64;
     while let Some(chunk) = cu::check!(response.chunk().await, "failed to read response chunk")? {
         writer.write_all(&chunk)?;
+        received += chunk.len() as u64;
         cu::progress!(bar += chunk.len());
     }
     writer.flush()?;
+    if let Some(l) = length.filter(|l| *l != received) {
+        cu::bail!("connection closed after {received} of {l} bytes");
+    }
     Ok(())
 }
+
+/// Append a suffix to the file name of the path
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Mutex;
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::opfs;
+
+    /// Local stand-in for a file host
+    #[derive(Default)]
+    struct TestServer {
+        body: Vec<u8>,
+        /// Support range requests
+        ranges: bool,
+        /// Cut off the first response after this many bytes of the body
+        fail_after: Option<usize>,
+        /// Respond with 404 to everything
+        not_found: bool,
+        /// Range headers of the GET requests received
+        ranges_received: Mutex<Vec<Option<String>>>,
+        requests: AtomicUsize,
+    }
+
+    impl TestServer {
+        /// Start serving on localhost and return the URL
+        fn serve(self) -> (Arc<Self>, String) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+            let server = Arc::new(self);
+            let server2 = Arc::clone(&server);
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let server = Arc::clone(&server2);
+                    std::thread::spawn(move || server.handle(stream.unwrap()));
+                }
+            });
+            (server, url)
+        }
+
+        fn handle(&self, mut stream: TcpStream) {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut range = None;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim();
+                if line.is_empty() {
+                    break;
+                }
+                let header = line.split_once(':');
+                if let Some((_, value)) = header.filter(|(x, _)| x.eq_ignore_ascii_case("range")) {
+                    range = Some(value.trim().to_string());
+                }
+            }
+            let is_head = request_line.starts_with("HEAD");
+            if !is_head {
+                self.ranges_received.lock().unwrap().push(range.clone());
+            }
+            if self.not_found {
+                write!(
+                    stream,
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                return;
+            }
+            let len = self.body.len();
+            let range = range.filter(|_| self.ranges).and_then(|x| {
+                let (start, end) = x.strip_prefix("bytes=")?.split_once('-')?;
+                let start = start.parse::<usize>().ok()?;
+                let end = end.parse::<usize>().unwrap_or(len - 1);
+                Some((start, end))
+            });
+            let accept_ranges = if self.ranges {
+                "Accept-Ranges: bytes\r\n"
+            } else {
+                ""
+            };
+            let (status, body) = match range {
+                Some((start, _)) if start >= len => {
+                    write!(stream, "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+                    return;
+                }
+                Some((start, end)) => (
+                    format!("206 Partial Content\r\nContent-Range: bytes {start}-{end}/{len}"),
+                    &self.body[start..=end],
+                ),
+                None => ("200 OK".to_string(), &self.body[..]),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\n{accept_ranges}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            if is_head {
+                return;
+            }
+            let is_first = self.requests.fetch_add(1, Ordering::SeqCst) == 0;
+            let body = match self.fail_after {
+                Some(n) if is_first => &body[..n],
+                _ => body,
+            };
+            let _ = stream.write_all(body);
+        }
+    }
+
+    fn test_body() -> Vec<u8> {
+        (0..100_000u32).map(|x| (x % 251) as u8).collect()
+    }
+
+    /// Make an empty directory for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shaft-download-test-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sha256(dir: &Path, body: &[u8]) -> String {
+        let path = dir.join("expected");
+        std::fs::write(&path, body).unwrap();
+        opfs::file_sha256(&path, None).unwrap()
+    }
+
+    fn test_client() -> Client {
+        Client::builder().build().unwrap()
+    }
+
+    #[test]
+    fn download_full() {
+        let body = test_body();
+        let dir = test_dir("full");
+        let checksum = Integrity::sha256(sha256(&dir, &body));
+        let (_, url) = TestServer {
+            body: body.clone(),
+            ranges: true,
+            ..Default::default()
+        }
+        .serve();
+        let target = dir.join("file.bin");
+        let bar = cu::progress("test").spawn();
+        download_verified(&test_client(), &url, &target, &checksum, &bar).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), body);
+        assert!(!with_suffix(&target, ".part").exists());
+    }
+
+    #[test]
+    fn download_resume() {
+        let body = test_body();
+        let dir = test_dir("resume");
+        let checksum = Integrity::sha256(sha256(&dir, &body));
+        let (server, url) = TestServer {
+            body: body.clone(),
+            ranges: true,
+            fail_after: Some(30_000),
+            ..Default::default()
+        }
+        .serve();
+        let target = dir.join("file.bin");
+        let bar = cu::progress("test").spawn();
+        let client = test_client();
+        let e = download_verified(&client, &url, &target, &checksum, &bar).unwrap_err();
+        // the connection is cut off, which can be retried
+        assert!(e.downcast_ref::<FatalDownloadError>().is_none());
+        assert!(!target.exists());
+        let part_len = with_suffix(&target, ".part").metadata().unwrap().len();
+        assert!(part_len > 0);
+
+        download_verified(&client, &url, &target, &checksum, &bar).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), body);
+        let ranges = server.ranges_received.lock().unwrap();
+        assert_eq!(ranges.last().unwrap(), &Some(format!("bytes={part_len}-")));
+    }
+
+    #[test]
+    fn download_restart_without_range_support() {
+        let body = test_body();
+        let dir = test_dir("restart");
+        let checksum = Integrity::sha256(sha256(&dir, &body));
+        let (_, url) = TestServer {
+            body: body.clone(),
+            fail_after: Some(30_000),
+            ..Default::default()
+        }
+        .serve();
+        let target = dir.join("file.bin");
+        let bar = cu::progress("test").spawn();
+        let client = test_client();
+        assert!(download_verified(&client, &url, &target, &checksum, &bar).is_err());
+        download_verified(&client, &url, &target, &checksum, &bar).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), body);
+    }
+
+    #[test]
+    fn download_bad_status() {
+        let dir = test_dir("status");
+        let (_, url) = TestServer {
+            not_found: true,
+            ..Default::default()
+        }
+        .serve();
+        let target = dir.join("file.bin");
+        let bar = cu::progress("test").spawn();
+        let checksum = Integrity::sha256(sha256(&dir, b""));
+        let e = download_verified(&test_client(), &url, &target, &checksum, &bar).unwrap_err();
+        assert!(e.downcast_ref::<FatalDownloadError>().is_some());
+        assert!(!target.exists());
+        assert!(!with_suffix(&target, ".part").exists());
+    }
+
+    #[test]
+    fn download_checksum_mismatch() {
+        let body = test_body();
+        let dir = test_dir("checksum");
+        let checksum = Integrity::sha256(sha256(&dir, b"something else"));
+        let (_, url) = TestServer {
+            body,
+            ranges: true,
+            ..Default::default()
+        }
+        .serve();
+        let target = dir.join("file.bin");
+        let bar = cu::progress("test").spawn();
+        let e = download_verified(&test_client(), &url, &target, &checksum, &bar).unwrap_err();
+        assert!(e.downcast_ref::<FatalDownloadError>().is_some());
+        assert!(!target.exists());
+        assert!(!with_suffix(&target, ".part").exists());
+    }
+
+    #[test]
+    fn download_in_chunks() {
+        let body = test_body();
+        let dir = test_dir("chunks");
+        let (server, url) = TestServer {
+            body: body.clone(),
+            ranges: true,
+            ..Default::default()
+        }
+        .serve();
+        let part = dir.join("file.bin.part");
+        let bar = cu::progress("test").spawn();
+        download_chunked(&test_client(), &url, &part, body.len() as u64, &bar).unwrap();
+        assert_eq!(std::fs::read(&part).unwrap(), body);
+        assert_eq!(
+            server.ranges_received.lock().unwrap().len(),
+            CHUNK_COUNT as usize
+        );
+    }
+}
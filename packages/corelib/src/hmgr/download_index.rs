@@ -0,0 +1,107 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+use cu::pre::*;
+
+use crate::hmgr::Digest;
+use crate::{hmgr, opfs};
+
+/// Index of files in HOME/download, stored in HOME/download/index.json
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DownloadIndex {
+    /// File name in the download directory to the entry
+    #[serde(flatten)]
+    pub entries: BTreeMap<String, DownloadEntry>,
+}
+
+/// Where a file in the download cache came from
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DownloadEntry {
+    /// URL the file is downloaded from, before rewriting
+    pub url: String,
+    /// Digest of the file, in `<algorithm>:<hex>` format
+    pub digest: String,
+    /// Size of the file in bytes
+    #[serde(default)]
+    pub size: u64,
+    /// The package that downloaded the file
+    #[serde(default)]
+    pub package: Option<String>,
+    /// Version of the package installed with the file
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Seconds since UNIX epoch when the file was last used
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+/// Serializes updates to the index file, since packages may be downloaded in parallel
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+impl DownloadIndex {
+    #[cu::context("failed to load download index")]
+    pub fn load() -> cu::Result<Self> {
+        let path = hmgr::paths::download_index_json();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = cu::fs::read_string(path)?;
+        Ok(json::parse(&content)?)
+    }
+
+    #[cu::context("failed to save download index")]
+    pub fn save(&self) -> cu::Result<()> {
+        cu::fs::make_dir(hmgr::paths::download_root())?;
+        cu::fs::write_json_pretty(hmgr::paths::download_index_json(), self)
+    }
+
+    /// Load, update and save the index
+    pub fn update(f: impl FnOnce(&mut Self)) -> cu::Result<()> {
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut index = Self::load()?;
+        f(&mut index);
+        index.save()
+    }
+
+    /// Record the package and version that use the files.
+    /// The version is cleared if the package changed and no version is given
+    pub fn set_owner(files: &BTreeSet<String>, package: &str, version: Option<&str>) {
+        if files.is_empty() {
+            return;
+        }
+        let result = Self::update(|index| {
+            for file in files {
+                let Some(entry) = index.entries.get_mut(file) else {
+                    continue;
+                };
+                if entry.package.as_deref() != Some(package) {
+                    entry.package = Some(package.to_string());
+                    entry.version = None;
+                }
+                if let Some(version) = version {
+                    entry.version = Some(version.to_string());
+                }
+            }
+        });
+        if let Err(e) = result {
+            // the index is only for managing the cache
+            cu::warn!("{e:?}");
+        }
+    }
+}
+
+/// Record a file in the download cache that is downloaded or used
+pub(crate) fn record_download(name: &str, url: &str, digest: &Digest, size: u64) {
+    let result = DownloadIndex::update(|index| {
+        let entry = index.entries.entry(name.to_string()).or_default();
+        entry.url = url.to_string();
+        entry.digest = digest.to_string();
+        entry.size = size;
+        entry.timestamp = opfs::now_unix();
+    });
+    if let Err(e) = result {
+        // the index is only for managing the cache
+        cu::warn!("{e:?}");
+    }
+}
@@ -52,6 +52,12 @@ pub fn add_env_assert<I: IntoIterator<Item = (String, String)>>(iter: I) -> cu::
     Ok(())
 }
 
+/// Get the environment asserts in HOME/environment.json that are not verified yet
+#[inline(always)]
+pub fn env_asserts() -> cu::Result<BTreeMap<String, String>> {
+    load_env_json()
+}
+
 /// Load and check if the current environment matches assertions in HOME/environment.json
 #[inline(always)]
 pub fn init_env() -> cu::Result<()> {
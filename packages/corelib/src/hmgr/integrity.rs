@@ -0,0 +1,326 @@
+use std::io::Read as _;
+use std::path::Path;
+use std::sync::Arc;
+
+use cu::pre::*;
+use reqwest::StatusCode;
+
+use crate::hmgr::network::{self, Source};
+use crate::{hmgr, opfs};
+
+/// How to verify a download. At least one digest is always required,
+/// since it identifies the file in the download cache. The signature
+/// is an additional check with a public key pinned in the registry.
+///
+/// A `&str` converts to a SHA256 digest, which is what most packages use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Integrity {
+    digests: Vec<Digest>,
+    signature: Option<Signature>,
+}
+
+impl Integrity {
+    /// Verify with a SHA256 checksum
+    pub fn sha256(hex: impl Into<String>) -> Self {
+        Self::digest(Digest::new(HashAlgorithm::Sha256, hex))
+    }
+    /// Verify with a SHA512 checksum
+    pub fn sha512(hex: impl Into<String>) -> Self {
+        Self::digest(Digest::new(HashAlgorithm::Sha512, hex))
+    }
+    /// Verify with a BLAKE3 hash
+    pub fn blake3(hex: impl Into<String>) -> Self {
+        Self::digest(Digest::new(HashAlgorithm::Blake3, hex))
+    }
+    fn digest(digest: Digest) -> Self {
+        Self {
+            digests: vec![digest],
+            signature: None,
+        }
+    }
+    /// Also verify with a SHA512 checksum
+    pub fn and_sha512(mut self, hex: impl Into<String>) -> Self {
+        self.digests.push(Digest::new(HashAlgorithm::Sha512, hex));
+        self
+    }
+    /// Also verify with a BLAKE3 hash
+    pub fn and_blake3(mut self, hex: impl Into<String>) -> Self {
+        self.digests.push(Digest::new(HashAlgorithm::Blake3, hex));
+        self
+    }
+    /// Also verify with a minisign signature downloaded from the URL,
+    /// with the base64 public key (the second line of `minisign.pub`)
+    pub fn minisign(mut self, url: impl Into<String>, public_key: &'static str) -> Self {
+        self.signature = Some(Signature::Minisign {
+            url: url.into(),
+            public_key,
+        });
+        self
+    }
+    /// Also verify with a GPG detached signature downloaded from the URL,
+    /// with the ASCII-armored public key. Requires `gpg` to be installed
+    pub fn gpg(mut self, url: impl Into<String>, public_key: &'static str) -> Self {
+        self.signature = Some(Signature::Gpg {
+            url: url.into(),
+            public_key,
+        });
+        self
+    }
+
+    /// The first digest, used to identify the file in the download cache
+    pub fn primary(&self) -> &Digest {
+        &self.digests[0]
+    }
+
+    /// Check the file against all the digests
+    pub fn verify_digests(&self, path: &Path, bar: Option<Arc<cu::ProgressBar>>) -> cu::Result<()> {
+        for digest in &self.digests {
+            digest.verify(path, bar.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Download the signature and verify the file with it, if there is one
+    pub(crate) fn verify_signature(&self, path: &Path) -> cu::Result<()> {
+        match &self.signature {
+            None => Ok(()),
+            Some(signature) => signature.verify(path),
+        }
+    }
+}
+
+impl From<&str> for Integrity {
+    fn from(value: &str) -> Self {
+        Self::sha256(value)
+    }
+}
+
+/// Hash algorithm of a digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum HashAlgorithm {
+    #[display("sha256")]
+    Sha256,
+    #[display("sha512")]
+    Sha512,
+    #[display("blake3")]
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Hash the file and return the lowercase hex string
+    pub fn hash_file(self, path: &Path, bar: Option<Arc<cu::ProgressBar>>) -> cu::Result<String> {
+        match self {
+            Self::Sha256 => opfs::file_sha256(path, bar),
+            Self::Sha512 => opfs::file_sha512(path, bar),
+            Self::Blake3 => opfs::file_blake3(path, bar),
+        }
+    }
+}
+
+/// Expected hash of a file, displayed as `<algorithm>:<hex>`
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[display("{algorithm}:{hex}")]
+pub struct Digest {
+    pub algorithm: HashAlgorithm,
+    /// Lowercase hex string
+    pub hex: String,
+}
+
+impl Digest {
+    pub fn new(algorithm: HashAlgorithm, hex: impl Into<String>) -> Self {
+        Self {
+            algorithm,
+            hex: hex.into().to_ascii_lowercase(),
+        }
+    }
+
+    /// Parse from `<algorithm>:<hex>`. A hex string without the algorithm is SHA256
+    pub fn parse(s: &str) -> cu::Result<Self> {
+        let (algorithm, hex) = match s.split_once(':') {
+            None => (HashAlgorithm::Sha256, s),
+            Some(("sha256", hex)) => (HashAlgorithm::Sha256, hex),
+            Some(("sha512", hex)) => (HashAlgorithm::Sha512, hex),
+            Some(("blake3", hex)) => (HashAlgorithm::Blake3, hex),
+            Some((algorithm, _)) => cu::bail!("unknown hash algorithm '{algorithm}'"),
+        };
+        Ok(Self::new(algorithm, hex))
+    }
+
+    /// Hash the file and check that it matches
+    pub fn verify(&self, path: &Path, bar: Option<Arc<cu::ProgressBar>>) -> cu::Result<()> {
+        let actual = self.algorithm.hash_file(path, bar)?;
+        if actual != self.hex {
+            cu::bail!(
+                "{} mismatch for '{}': expected {}, got {actual}",
+                self.algorithm,
+                path.display(),
+                self.hex
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Detached signature of a download
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Signature {
+    Minisign {
+        url: String,
+        public_key: &'static str,
+    },
+    Gpg {
+        url: String,
+        public_key: &'static str,
+    },
+}
+
+impl Signature {
+    #[cu::context("failed to verify signature of '{}'", path.display())]
+    fn verify(&self, path: &Path) -> cu::Result<()> {
+        match self {
+            Self::Minisign { url, public_key } => {
+                let signature = fetch_signature(url)?;
+                verify_minisign(path, &signature, public_key)
+            }
+            Self::Gpg { url, public_key } => {
+                let signature = fetch_signature(url)?;
+                verify_gpg(path, &signature, public_key)
+            }
+        }
+    }
+}
+
+fn verify_minisign(path: &Path, signature: &[u8], public_key: &str) -> cu::Result<()> {
+    use minisign_verify::{PublicKey, Signature};
+    let public_key = cu::check!(
+        PublicKey::from_base64(public_key),
+        "invalid minisign public key"
+    )?;
+    let signature = cu::check!(
+        Signature::decode(&String::from_utf8_lossy(signature)),
+        "invalid minisign signature"
+    )?;
+    let mut verifier = cu::check!(
+        public_key.verify_stream(&signature),
+        "unsupported minisign signature, only prehashed signatures are supported"
+    )?;
+    let mut reader = cu::fs::reader(path)?;
+    let mut buf = vec![0u8; 4096000].into_boxed_slice();
+    loop {
+        let i = reader.read(&mut buf)?;
+        if i == 0 {
+            break;
+        }
+        verifier.update(&buf[..i]);
+    }
+    cu::check!(verifier.finalize(), "minisign signature does not match")?;
+    cu::debug!("minisign signature verified: '{}'", path.display());
+    Ok(())
+}
+
+fn verify_gpg(path: &Path, signature: &[u8], public_key: &str) -> cu::Result<()> {
+    // use a separate home, so only the pinned key is trusted
+    let temp_name = format!(
+        "gpg-{}",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let gpg_home = hmgr::paths::temp_dir(&temp_name);
+    cu::fs::make_dir_empty(&gpg_home)?;
+    let result = verify_gpg_in(&gpg_home, path, signature, public_key);
+    hmgr::paths::clean_temp_dir(&temp_name);
+    result?;
+    cu::debug!("gpg signature verified: '{}'", path.display());
+    Ok(())
+}
+
+fn verify_gpg_in(
+    gpg_home: &Path,
+    path: &Path,
+    signature: &[u8],
+    public_key: &str,
+) -> cu::Result<()> {
+    let gpg = cu::which("gpg")?;
+    let key_path = gpg_home.join("key.asc");
+    let signature_path = gpg_home.join("file.sig");
+    cu::fs::write(&key_path, public_key)?;
+    cu::fs::write(&signature_path, signature)?;
+    gpg.command()
+        .add(cu::args![
+            "--homedir",
+            gpg_home,
+            "--batch",
+            "--import",
+            &key_path
+        ])
+        .stdout(cu::lv::D)
+        .stderr(cu::lv::D)
+        .stdin_null()
+        .wait_nz()?;
+    let result = gpg
+        .command()
+        .add(cu::args![
+            "--homedir",
+            gpg_home,
+            "--batch",
+            "--verify",
+            &signature_path,
+            path
+        ])
+        .stdout(cu::lv::D)
+        .stderr(cu::lv::D)
+        .stdin_null()
+        .wait_nz();
+    cu::check!(result, "gpg signature does not match")?;
+    Ok(())
+}
+
+/// Get the content of a signature file, which is small
+fn fetch_signature(url: &str) -> cu::Result<Vec<u8>> {
+    let url = match network::resolve_source(url)? {
+        Source::Local(path) => return cu::fs::read(path),
+        Source::Remote(url) => url,
+    };
+    if hmgr::is_offline() {
+        cu::bail!("cannot download signature in offline mode: {url}");
+    }
+    let client = network::client()?.clone();
+    cu::debug!("downloading signature from {url}");
+    cu::co::run(async move {
+        let response = cu::check!(client.get(&url).send().await, "failed to send request")?;
+        let status = response.status();
+        if status != StatusCode::OK {
+            cu::bail!("server responded with status {status}: {url}");
+        }
+        let bytes = cu::check!(response.bytes().await, "failed to read response")?;
+        Ok(bytes.to_vec())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_digest() {
+        let err = Digest::parse("SHA512:ABCD").unwrap_err();
+        assert!(format!("{err}").contains("SHA512"));
+        let digest = Digest::parse("blake3:ABCD").unwrap();
+        assert_eq!(digest.algorithm, HashAlgorithm::Blake3);
+        assert_eq!(digest.to_string(), "blake3:abcd");
+        let digest = Digest::parse("abcd").unwrap();
+        assert_eq!(digest.algorithm, HashAlgorithm::Sha256);
+    }
+
+    #[test]
+    fn verify_all_digests() {
+        let path = std::env::temp_dir().join("shaft-integrity-test-empty");
+        std::fs::write(&path, b"").unwrap();
+        let integrity =
+            Integrity::sha256("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+                .and_sha512("cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e")
+                .and_blake3("AF1349B9F5F9A1A6A0404DEA36DCC9499BCB25C9ADC112B7CC9A93CAE41F3262");
+        integrity.verify_digests(&path, None).unwrap();
+        let integrity = integrity.and_blake3("00");
+        assert!(integrity.verify_digests(&path, None).is_err());
+    }
+}
@@ -1,15 +1,18 @@
 use std::collections::{BTreeMap, BTreeSet};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use cu::pre::*;
 use shaftim_build::{ShimCommand, ShimConfig};
 
+use crate::hmgr::link::{self, LinkStrategy};
 use crate::{bin_name, hmgr, opfs};
 
 #[derive(Default)]
 pub struct ItemMgr {
     items: Vec<ItemEntry>,
+    /// How each file in the bin directory is linked
+    links: BTreeMap<String, LinkStrategy>,
     skip_reinvocation: bool,
     reinvocation_needed: bool,
     dirty: bool,
@@ -27,6 +30,7 @@ impl ItemMgr {
         let Ok(items) = cu::fs::read_string(config_path) else {
             return Ok(Self {
                 items: vec![],
+                links: BTreeMap::new(),
                 skip_reinvocation: false,
                 reinvocation_needed: false,
                 dirty: true,
@@ -48,6 +52,7 @@ impl ItemMgr {
         };
         Ok(Self {
             items,
+            links: Self::load_links(),
             skip_reinvocation: false,
             reinvocation_needed: false,
             dirty: false,
@@ -58,6 +63,23 @@ impl ItemMgr {
             cmd_dirty: false,
         })
     }
+
+    /// Load how the files in the bin directory are linked.
+    /// Files not recorded are hard links from older versions
+    fn load_links() -> BTreeMap<String, LinkStrategy> {
+        let path = hmgr::paths::items_links_json();
+        let Ok(content) = cu::fs::read_string(path) else {
+            return BTreeMap::new();
+        };
+        match json::parse(&content) {
+            Ok(x) => x,
+            Err(e) => {
+                cu::warn!("failed to load link strategies, assuming hard links: {e}");
+                BTreeMap::new()
+            }
+        }
+    }
+
     pub fn skip_reinvocation(&mut self, skip: bool) {
         self.skip_reinvocation = skip;
     }
@@ -147,7 +169,8 @@ impl ItemMgr {
             let bin_root = hmgr::paths::bin_root();
             for bin in bin_to_remove {
                 cu::progress!(bar += 1, "{bin}");
-                if let Err(e) = opfs::safe_remove_link(&bin_root.join(bin)) {
+                let strategy = self.links.remove(&bin).unwrap_or_default();
+                if let Err(e) = link::remove_link(&bin_root.join(bin), strategy) {
                     cu::warn!("failed to remove old link: {e}");
                 }
             }
@@ -229,6 +252,7 @@ impl ItemMgr {
 
         let config_path = hmgr::paths::items_config_json();
         cu::fs::write_json_pretty(config_path, &self.items)?;
+        cu::fs::write_json_pretty(hmgr::paths::items_links_json(), &self.links)?;
 
         if !self.skip_reinvocation && self.reinvocation_needed {
             hmgr::require_envchange_reinvocation()?;
@@ -291,20 +315,23 @@ impl ItemMgr {
                 // assume existing file is from linking previously
                 continue;
             }
-            link_paths.push((link_path, to, non_exe));
-        }
-        let link_paths2: Vec<(&Path, &Path)> = link_paths
-            .iter()
-            .map(|(x, y, _)| (x.as_path(), y.as_ref()))
-            .collect();
-
-        opfs::hardlink_files(&link_paths2)?;
-
-        #[cfg(not(windows))]
-        {
-            for (from, _, non_exe) in link_paths {
-                if !non_exe {
-                    opfs::set_executable(&from)?;
+            link_paths.push((from.clone(), link_path, to.clone(), *non_exe));
+        }
+        // scripts can't replace non-executables
+        for non_exe in [false, true] {
+            let group: Vec<_> = link_paths.iter().filter(|x| x.3 == non_exe).collect();
+            let paths: Vec<(&Path, &Path)> = group
+                .iter()
+                .map(|(_, x, y, _)| (x.as_path(), y.as_ref()))
+                .collect();
+            let strategies = link::create_links(&paths, !non_exe)?;
+            for ((name, link_path, _, _), strategy) in group.into_iter().zip(strategies) {
+                if strategy != LinkStrategy::Hardlink {
+                    cu::debug!("linked '{name}' with {strategy}");
+                }
+                self.links.insert(name.clone(), strategy);
+                if cfg!(not(windows)) && !non_exe {
+                    opfs::set_executable(link_path)?;
                 }
             }
         }
@@ -431,6 +458,61 @@ impl ItemMgr {
         Ok(())
     }
 
+    /// Get the items added by the package
+    pub fn package_items(&self, package: &str) -> Vec<ItemEntry> {
+        self.items
+            .iter()
+            .filter(|x| x.package == package)
+            .cloned()
+            .collect()
+    }
+
+    /// Replace the items of the package with items previously taken with `package_items`
+    pub fn restore_package(
+        &mut self,
+        package: &str,
+        entries: Vec<ItemEntry>,
+        bar: Option<&Arc<cu::ProgressBar>>,
+    ) -> cu::Result<()> {
+        self.remove_package(package, bar)?;
+        for entry in entries {
+            self.add_item(package, entry.item, entry.priority);
+        }
+        Ok(())
+    }
+
+    /// Get the user environment variables set by the items
+    #[inline(always)]
+    pub fn env_vars(&self) -> cu::Result<Vec<(String, String)>> {
+        self.build_env_map()
+    }
+
+    /// Check the links and shims in the bin directory exist and
+    /// point to the expected target
+    pub fn check_links(&self) -> Vec<BrokenLink> {
+        let bin_root = hmgr::paths::bin_root();
+        let shim_binary = hmgr::paths::shim_binary();
+        let mut out = vec![];
+        for entry in &self.items {
+            let (name, target) = match &entry.item {
+                Item::LinkBin(name, target, _) => (name, Path::new(target)),
+                Item::ShimBin(name, _) => (name, shim_binary.as_path()),
+                _ => continue,
+            };
+            let path = bin_root.join(name);
+            let strategy = self.links.get(name).copied().unwrap_or_default();
+            let Some(reason) = link::link_problem(&path, target, strategy) else {
+                continue;
+            };
+            out.push(BrokenLink {
+                package: entry.package.clone(),
+                path,
+                reason,
+            });
+        }
+        out
+    }
+
     fn build_env_map(&self) -> cu::Result<Vec<(String, String)>> {
         let mut seen_key = BTreeSet::new();
         let mut envs = vec![];
@@ -612,16 +694,32 @@ impl ItemMgr {
             .iter()
             .map(|x| (x.as_path(), shim_binary.as_path()))
             .collect::<Vec<_>>();
-        cu::check!(
-            opfs::hardlink_files(&link_paths),
-            "failed to create hardlinks for shim binaries"
+        // the shim finds the command by the name it's invoked with,
+        // so it can't be a script
+        let strategies = cu::check!(
+            link::create_links(&link_paths, false),
+            "failed to create links for shim binaries"
         )?;
+        for (name, strategy) in shim_config.keys().zip(strategies) {
+            self.links.insert(name.clone(), strategy);
+        }
 
         self.shim_dirty = false;
         Ok(())
     }
 }
 
+/// A link or shim in the bin directory that is not in the expected state
+#[derive(Debug, Clone)]
+pub struct BrokenLink {
+    /// Package that added the link
+    pub package: String,
+    /// Path of the link
+    pub path: PathBuf,
+    /// Description of the problem
+    pub reason: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ItemEntry {
     package: String,
@@ -0,0 +1,259 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use cu::pre::*;
+
+use crate::opfs;
+
+/// How a file in the bin directory is linked to its target
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkStrategy {
+    /// Hard link, the target must be on the same file system
+    #[default]
+    #[display("hardlink")]
+    Hardlink,
+    /// Symbolic link to the absolute path of the target. Requires sudo on Windows
+    #[display("symlink")]
+    Symlink,
+    /// Copy of the target
+    #[display("copy")]
+    Copy,
+    /// Shell script that executes the target. Not supported on Windows,
+    /// and never used for shims or non-executables
+    #[display("script")]
+    Script,
+}
+
+/// Settings in the `[link]` section of `core.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LinkConfig {
+    /// Preferred way to link binaries into the bin directory
+    pub strategy: LinkStrategy,
+    /// If the preferred strategy fails, try hardlink, symlink (not on Windows)
+    /// and copy, in that order
+    pub fallback: bool,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            strategy: LinkStrategy::Hardlink,
+            fallback: true,
+        }
+    }
+}
+
+static CONFIG: OnceLock<LinkConfig> = OnceLock::new();
+
+/// Set the link config. Must be called before any item is built
+/// for the config to take effect
+pub fn init_link(config: LinkConfig) {
+    cu::debug!("link config: {config:?}");
+    if CONFIG.set(config).is_err() {
+        cu::warn!("link config is already initialized");
+    }
+}
+
+fn config() -> &'static LinkConfig {
+    CONFIG.get_or_init(Default::default)
+}
+
+/// Get the strategies to try in order
+fn strategies(allow_script: bool) -> Vec<LinkStrategy> {
+    let config = config();
+    let mut out = vec![config.strategy];
+    if config.fallback {
+        let fallbacks: &[LinkStrategy] = if cfg!(windows) {
+            &[LinkStrategy::Hardlink, LinkStrategy::Copy]
+        } else {
+            &[
+                LinkStrategy::Hardlink,
+                LinkStrategy::Symlink,
+                LinkStrategy::Copy,
+            ]
+        };
+        for strategy in fallbacks {
+            if !out.contains(strategy) {
+                out.push(*strategy);
+            }
+        }
+    }
+    if !allow_script {
+        out.retain(|x| *x != LinkStrategy::Script);
+    }
+    out
+}
+
+/// Create links with the configured strategy, falling back to the other strategies
+/// for the links that fail. `from` is where the link will be and `to` is the target.
+///
+/// Scripts are only allowed if the target doesn't depend on the name it's invoked with.
+/// Returns the strategy used for each link
+#[cu::context("failed to create links")]
+pub(crate) fn create_links(
+    paths: &[(&Path, &Path)],
+    allow_script: bool,
+) -> cu::Result<Vec<LinkStrategy>> {
+    let mut out: Vec<Option<LinkStrategy>> = vec![None; paths.len()];
+    let mut last_error = None;
+    for strategy in strategies(allow_script) {
+        let pending: Vec<usize> = (0..paths.len()).filter(|i| out[*i].is_none()).collect();
+        if pending.is_empty() {
+            break;
+        }
+        let pending_paths: Vec<(&Path, &Path)> = pending.iter().map(|i| paths[*i]).collect();
+        match create_links_with(&pending_paths, strategy) {
+            Ok(()) => {
+                for i in pending {
+                    out[i] = Some(strategy);
+                }
+            }
+            Err(e) => {
+                cu::debug!("failed to create links with {strategy}: {e:?}");
+                // some of the links could be created before the error
+                for i in pending {
+                    let (from, to) = paths[i];
+                    if link_problem(from, to, strategy).is_none() {
+                        out[i] = Some(strategy);
+                    }
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+    let mut strategies = Vec::with_capacity(paths.len());
+    for (i, strategy) in out.into_iter().enumerate() {
+        match strategy {
+            Some(strategy) => strategies.push(strategy),
+            None => {
+                let (from, to) = paths[i];
+                if let Some(e) = last_error {
+                    cu::error!("{e:?}");
+                }
+                cu::bail!(
+                    "cannot link '{}' to '{}' with any strategy",
+                    from.display(),
+                    to.display()
+                );
+            }
+        }
+    }
+    Ok(strategies)
+}
+
+fn create_links_with(paths: &[(&Path, &Path)], strategy: LinkStrategy) -> cu::Result<()> {
+    match strategy {
+        LinkStrategy::Hardlink => opfs::hardlink_files(paths),
+        LinkStrategy::Symlink => opfs::symlink_files(paths),
+        LinkStrategy::Copy => {
+            for (from, to) in paths {
+                opfs::safe_remove_link(from)?;
+                cu::fs::copy(to, from)?;
+            }
+            Ok(())
+        }
+        LinkStrategy::Script => {
+            if cfg!(windows) {
+                cu::bail!("script links are not supported on Windows");
+            }
+            for (from, to) in paths {
+                opfs::safe_remove_link(from)?;
+                cu::fs::write(from, script_content(to)?)?;
+                opfs::set_executable(from)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn script_content(target: &Path) -> cu::Result<String> {
+    let target = target.normalize()?;
+    let target = target.as_utf8()?;
+    if target.contains('\'') {
+        cu::bail!("quote (') in path is not allowed: {target}");
+    }
+    Ok(format!("#!/bin/sh\nexec '{target}' \"$@\"\n"))
+}
+
+/// Remove a file in the bin directory that is created with the strategy
+#[cu::context("failed to remove link: '{}'", path.display())]
+pub(crate) fn remove_link(path: &Path, strategy: LinkStrategy) -> cu::Result<()> {
+    match strategy {
+        // the target could be gone, so check the link itself
+        LinkStrategy::Symlink => {
+            if path.is_symlink() {
+                std::fs::remove_file(path)?;
+            }
+            Ok(())
+        }
+        // the file could be in use
+        LinkStrategy::Hardlink | LinkStrategy::Copy | LinkStrategy::Script => {
+            opfs::safe_remove_link(path)
+        }
+    }
+}
+
+/// Check if the file in the bin directory, created with the strategy,
+/// still links to the target. Returns the problem if not
+pub(crate) fn link_problem(path: &Path, target: &Path, strategy: LinkStrategy) -> Option<String> {
+    if !path.exists() {
+        return Some("link does not exist".to_string());
+    }
+    if !target.exists() {
+        return Some(format!(
+            "link target does not exist: '{}'",
+            target.display()
+        ));
+    }
+    let result = match strategy {
+        LinkStrategy::Hardlink => opfs::is_same_file(path, target),
+        LinkStrategy::Symlink => {
+            if !path.is_symlink() {
+                return Some("not a symbolic link".to_string());
+            }
+            opfs::is_same_file(path, target)
+        }
+        LinkStrategy::Copy => is_same_content(path, target),
+        LinkStrategy::Script => is_script_of(path, target),
+    };
+    match result {
+        Ok(true) => None,
+        Ok(false) => Some(format!(
+            "{strategy} does not point to '{}'",
+            target.display()
+        )),
+        Err(e) => Some(format!("failed to check link: {e}")),
+    }
+}
+
+fn is_script_of(path: &Path, target: &Path) -> cu::Result<bool> {
+    Ok(cu::fs::read_string(path)? == script_content(target)?)
+}
+
+fn is_same_content(a: &Path, b: &Path) -> cu::Result<bool> {
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    Ok(opfs::file_sha256(a, None)? == opfs::file_sha256(b, None)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fallback_order() {
+        let expected = if cfg!(windows) {
+            vec![LinkStrategy::Hardlink, LinkStrategy::Copy]
+        } else {
+            vec![
+                LinkStrategy::Hardlink,
+                LinkStrategy::Symlink,
+                LinkStrategy::Copy,
+            ]
+        };
+        assert_eq!(strategies(true), expected);
+    }
+}
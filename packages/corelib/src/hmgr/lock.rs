@@ -41,3 +41,21 @@ pub fn lock() -> cu::Result<HomeLock> {
     cu::debug!("acquired home directory lock");
     Ok(HomeLock(file))
 }
+
+/// Check if the lock file exists but is not held by any process,
+/// which means a previous run did not exit cleanly
+pub fn is_lock_stale() -> bool {
+    let path = hmgr::paths::dot_lock();
+    if !path.exists() {
+        return false;
+    }
+    let Ok(file) = File::open(&path) else {
+        return false;
+    };
+    if file.try_lock_exclusive().is_err() {
+        // held by another instance
+        return false;
+    }
+    let _ = file.unlock();
+    true
+}
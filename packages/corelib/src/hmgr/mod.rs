@@ -7,12 +7,22 @@ mod version;
 pub use version::*;
 mod download;
 pub use download::*;
+mod download_index;
+pub use download_index::{DownloadEntry, DownloadIndex};
+mod integrity;
+pub use integrity::{Digest, HashAlgorithm, Integrity};
+mod network;
+pub use network::{NetworkConfig, RewriteRule, init_network};
+mod link;
+pub use link::{LinkConfig, LinkStrategy, init_link};
+mod resources;
+pub use resources::PackageResources;
 pub mod config;
 pub mod repo;
 pub mod tools;
 
 mod item;
-pub use item::{Item, ItemMgr};
+pub use item::{BrokenLink, Item, ItemEntry, ItemMgr};
 
 mod clean;
 pub use clean::clean_home;
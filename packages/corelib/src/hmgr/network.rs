@@ -0,0 +1,300 @@
+use std::path::PathBuf;
+use std::sync::{LazyLock, OnceLock};
+
+use cu::pre::*;
+use reqwest::redirect::Policy;
+use reqwest::{Certificate, Client, Proxy, Url};
+
+/// Network settings in the `[network]` section of `core.toml`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NetworkConfig {
+    /// Only use cached downloads, same as the --offline flag
+    #[serde(default)]
+    pub offline: bool,
+    /// Rules to rewrite download URLs, the first matching rule is used
+    #[serde(default)]
+    pub rewrite: Vec<RewriteRule>,
+    /// Local directory to look for downloads before using the network.
+    /// `https://host/path` is looked up as `<mirror-dir>/host/path`
+    #[serde(default)]
+    pub mirror_dir: Option<PathBuf>,
+    /// Proxy for all HTTP(S) requests, e.g. `http://proxy.corp:8080`
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Paths to extra PEM certificates to trust
+    #[serde(default)]
+    pub ca_certs: Vec<PathBuf>,
+    /// If not empty, only download from these hosts (and their subdomains),
+    /// after URLs are rewritten
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Replace the prefix of download URLs, for example to use a mirror
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RewriteRule {
+    pub from: String,
+    /// Must be an `https://` or `file://` URL
+    pub to: String,
+}
+
+impl NetworkConfig {
+    /// Check the config can be used. Downloads are only made over HTTPS,
+    /// so rules can't rewrite to plain HTTP
+    pub fn validate(&self) -> cu::Result<()> {
+        for rule in &self.rewrite {
+            if !rule.to.starts_with("https://") && !rule.to.starts_with("file://") {
+                cu::bail!(
+                    "network.rewrite for '{}' must rewrite to an https:// or file:// URL, got '{}'",
+                    rule.from,
+                    rule.to
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+static CONFIG: OnceLock<NetworkConfig> = OnceLock::new();
+
+/// Set the network config. Must be called before anything is downloaded
+/// for the config to take effect
+pub fn init_network(config: NetworkConfig) {
+    cu::debug!("network config: {config:?}");
+    if CONFIG.set(config).is_err() {
+        cu::warn!("network config is already initialized");
+    }
+}
+
+fn config() -> &'static NetworkConfig {
+    CONFIG.get_or_init(Default::default)
+}
+
+static CLIENT: LazyLock<Result<Client, String>> =
+    LazyLock::new(|| build_client(config()).map_err(|x| format!("{x:?}")));
+
+pub(crate) fn client() -> cu::Result<&'static Client> {
+    let client: &Result<Client, String> = &CLIENT;
+    match client {
+        Ok(c) => Ok(c),
+        Err(e) => {
+            cu::bail!("error initializing https client: {e}")
+        }
+    }
+}
+
+fn build_client(config: &NetworkConfig) -> cu::Result<Client> {
+    let mut builder = Client::builder().gzip(true).https_only(true);
+    if let Some(proxy) = &config.proxy {
+        let proxy = cu::check!(Proxy::all(proxy), "invalid proxy '{proxy}'")?;
+        builder = builder.proxy(proxy);
+    }
+    for path in &config.ca_certs {
+        let pem = cu::fs::read(path)?;
+        let cert = cu::check!(
+            Certificate::from_pem(&pem),
+            "invalid certificate '{}'",
+            path.display()
+        )?;
+        builder = builder.add_root_certificate(cert);
+    }
+    if !config.allowed_hosts.is_empty() {
+        builder = builder.redirect(redirect_policy(config.allowed_hosts.clone()));
+    }
+    Ok(builder.build()?)
+}
+
+/// Same as the default redirect policy, but only follows redirects to the allowed hosts
+fn redirect_policy(allowed_hosts: Vec<String>) -> Policy {
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() >= 10 {
+            return attempt.error("too many redirects");
+        }
+        let host = attempt.url().host_str().unwrap_or_default();
+        if is_host_allowed(&allowed_hosts, host) {
+            return attempt.follow();
+        }
+        let message = format!(
+            "redirect to '{host}' is not allowed by network.allowed-hosts: {}",
+            attempt.url()
+        );
+        attempt.error(message)
+    })
+}
+
+/// Check if the host is one of the allowed hosts, or their subdomains
+fn is_host_allowed(allowed_hosts: &[String], host: &str) -> bool {
+    allowed_hosts
+        .iter()
+        .any(|x| host == x || host.ends_with(&format!(".{x}")))
+}
+
+/// Where to get a download from
+#[derive(Debug, PartialEq)]
+pub(crate) enum Source {
+    /// A file on this machine
+    Local(PathBuf),
+    /// A URL to download from
+    Remote(String),
+}
+
+/// Get where to download the URL from, with the mirrors and rewrite rules applied
+pub(crate) fn resolve_source(url: &str) -> cu::Result<Source> {
+    resolve_source_with(config(), url)
+}
+
+fn resolve_source_with(config: &NetworkConfig, url: &str) -> cu::Result<Source> {
+    let parsed = cu::check!(Url::parse(url), "invalid url '{url}'")?;
+    if let Some(mirror_dir) = &config.mirror_dir {
+        let mut path = mirror_dir.join(parsed.host_str().unwrap_or_default());
+        for segment in parsed.path_segments().into_iter().flatten() {
+            path.push(segment);
+        }
+        if path.is_file() {
+            cu::debug!("using mirror file '{}' for {url}", path.display());
+            return Ok(Source::Local(path));
+        }
+    }
+    let url = match config.rewrite.iter().find(|x| url.starts_with(&x.from)) {
+        Some(rule) => {
+            let rewritten = format!("{}{}", rule.to, &url[rule.from.len()..]);
+            cu::debug!("rewriting {url} to {rewritten}");
+            rewritten
+        }
+        None => url.to_string(),
+    };
+    let parsed = cu::check!(Url::parse(&url), "invalid url '{url}'")?;
+    if parsed.scheme() == "file" {
+        let path = match parsed.to_file_path() {
+            Ok(x) => x,
+            Err(_) => cu::bail!("invalid file url '{url}'"),
+        };
+        return Ok(Source::Local(path));
+    }
+    if !config.allowed_hosts.is_empty() {
+        let host = parsed.host_str().unwrap_or_default();
+        if !is_host_allowed(&config.allowed_hosts, host) {
+            cu::hint!(
+                "add a rewrite rule to use a mirror, or allow the host in network.allowed-hosts"
+            );
+            cu::bail!("downloading from '{host}' is not allowed: {url}");
+        }
+    }
+    Ok(Source::Remote(url))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_config() -> NetworkConfig {
+        NetworkConfig {
+            rewrite: vec![
+                RewriteRule {
+                    from: "https://github.com/".to_string(),
+                    to: "https://artifactory.corp/github/".to_string(),
+                },
+                RewriteRule {
+                    from: "https://example.com/files/".to_string(),
+                    to: "file:///srv/mirror/".to_string(),
+                },
+            ],
+            allowed_hosts: vec!["corp".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rewrite_to_mirror() {
+        let source = resolve_source_with(&test_config(), "https://github.com/a/b/x.zip").unwrap();
+        assert_eq!(
+            source,
+            Source::Remote("https://artifactory.corp/github/a/b/x.zip".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rewrite_to_file() {
+        let source =
+            resolve_source_with(&test_config(), "https://example.com/files/x.zip").unwrap();
+        assert_eq!(source, Source::Local(PathBuf::from("/srv/mirror/x.zip")));
+    }
+
+    #[test]
+    fn rewrite_to_http() {
+        assert!(test_config().validate().is_ok());
+        let mut config = test_config();
+        config.rewrite.push(RewriteRule {
+            from: "https://nodejs.org/".to_string(),
+            to: "http://mirror.corp/nodejs/".to_string(),
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn host_not_allowed() {
+        assert!(resolve_source_with(&test_config(), "https://nodejs.org/dist/x.zip").is_err());
+        let mut config = test_config();
+        config.allowed_hosts.clear();
+        assert!(resolve_source_with(&config, "https://nodejs.org/dist/x.zip").is_ok());
+    }
+
+    /// Serve redirects to `localhost` on `/out`, to `127.0.0.1` on `/in`,
+    /// and an empty file on other paths. Returns the base URL
+    fn serve_redirects() -> String {
+        use std::io::{BufRead as _, BufReader, Write as _};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let location = match path {
+                    "/out" => Some(format!("http://localhost:{port}/file")),
+                    "/in" => Some(format!("http://127.0.0.1:{port}/file")),
+                    _ => None,
+                };
+                let _ = match location {
+                    Some(location) => write!(
+                        stream,
+                        "HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    ),
+                    None => write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    ),
+                };
+            }
+        });
+        format!("http://127.0.0.1:{port}")
+    }
+
+    #[test]
+    fn redirect_host_not_allowed() {
+        let url = serve_redirects();
+        let client = reqwest::blocking::Client::builder()
+            .no_proxy()
+            .redirect(redirect_policy(vec!["127.0.0.1".to_string()]))
+            .build()
+            .unwrap();
+        let response = client.get(format!("{url}/in")).send().unwrap();
+        assert_eq!(response.status(), 200);
+        let error = client.get(format!("{url}/out")).send().unwrap_err();
+        assert!(error.is_redirect());
+    }
+}
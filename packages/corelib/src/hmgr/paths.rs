@@ -56,7 +56,8 @@ pub fn init_home_path(path: PathBuf) {
     }
 }
 
-pub(crate) fn home() -> &'static Path {
+/// Get the SHAFT_HOME directory path
+pub fn home() -> &'static Path {
     HOME_PATH
         .get()
         .expect("home not initialized; please debug with -vv")
@@ -74,6 +75,7 @@ home! {
     install_old_dir:          install_old_root / package,
     items_root:            "items",
     items_config_json:        items_root / "config.json",
+    items_links_json:         items_root / "links.json",
     init_ps1:                 items_root / "init.ps1",
     init_cmd:                 items_root / "init.cmd",
     init_bash:                items_root / "init.bash",
@@ -82,9 +84,14 @@ home! {
     shim_binary_old:          items_root / (crate::bin_name!("shaftim.old")),
     shim_config_json:         items_root / "shim_config.json",
     download_root:         "download",
+    download_index_json:      download_root / "index.json",
     temp_root:             "temp",
     temp_dir:                 temp_root / path,
     tools_root:            "tools",
+    history_root:          "history",
+    history_file:             history_root / file,
+    snapshot_root:         "snapshot",
+    snapshot_file:            snapshot_root / file,
     repo:                  "repo",
     dot_lock:              ".lock",
     environment_json:      "environment.json",
@@ -100,6 +107,14 @@ pub fn config_file(package: &str) -> PathBuf {
     p
 }
 
+/// HOME/install-old/<pkg>.json
+#[inline(always)]
+pub fn install_old_json(package: &str) -> PathBuf {
+    let mut p = install_old_root();
+    p.push(format!("{package}.json"));
+    p
+}
+
 /// HOME/download/<identifier_stem>-<url_hash>.<ext>
 #[inline(always)]
 pub fn download(identifier: impl AsRef<Path>, url: impl AsRef<str>) -> PathBuf {
@@ -6,6 +6,9 @@ static SHAFT_REPO: &str = "https://github.com/Pistonite/shaft";
 
 /// Build shaft from source locally and update the current executable
 pub fn local_update() -> cu::Result<()> {
+    if hmgr::is_offline() {
+        cu::bail!("cannot upgrade in offline mode");
+    }
     let repo_path = hmgr::paths::repo();
 
     if !repo_path.exists() {
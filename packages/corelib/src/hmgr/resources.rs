@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use cu::pre::*;
+
+/// Resources in HOME used by a package, which are not removed with the package
+/// unless it is purged
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageResources {
+    /// File names in HOME/download
+    #[serde(default)]
+    pub downloads: BTreeSet<String>,
+    /// Ids of version cache entries
+    #[serde(default)]
+    pub version_ids: BTreeSet<String>,
+}
+
+thread_local! {
+    static TRACKER: RefCell<Option<PackageResources>> = const { RefCell::new(None) };
+}
+
+impl PackageResources {
+    /// Start tracking resources used on the current thread
+    pub fn begin() {
+        TRACKER.with_borrow_mut(|x| *x = Some(Self::default()));
+    }
+    /// Stop tracking and take the resources used since `begin`
+    pub fn end() -> Self {
+        TRACKER.with_borrow_mut(|x| x.take()).unwrap_or_default()
+    }
+    /// Add resources from another set
+    pub fn extend(&mut self, other: Self) {
+        self.downloads.extend(other.downloads);
+        self.version_ids.extend(other.version_ids);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty() && self.version_ids.is_empty()
+    }
+    pub(crate) fn record_download(path: &Path) {
+        let Some(name) = path.file_name().and_then(|x| x.to_str()) else {
+            return;
+        };
+        TRACKER.with_borrow_mut(|x| {
+            if let Some(resources) = x {
+                resources.downloads.insert(name.to_string());
+            }
+        });
+    }
+    pub(crate) fn record_version(id: &str) {
+        TRACKER.with_borrow_mut(|x| {
+            if let Some(resources) = x {
+                resources.version_ids.insert(id.to_string());
+            }
+        });
+    }
+}
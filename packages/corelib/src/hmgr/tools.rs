@@ -20,8 +20,13 @@ pub fn ensure_unpacked() -> cu::Result<()> {
 fn do_unpack() -> cu::Result<()> {
     cu::info!("unpacking tools...");
     let tools_path = hmgr::paths::tools_root();
+    let options = opfs::ExtractOptions {
+        clean: true,
+        file_mode: opfs::FileMode::Preserve,
+        ..Default::default()
+    };
     cu::check!(
-        opfs::untargz_read(TOOLS_TAR_GZ, &tools_path, true /* clean */),
+        opfs::untargz_read_with(TOOLS_TAR_GZ, &tools_path, &options),
         "failed to unpack tools"
     )?;
     _gen::TOOLS_VERSION.update()?;
@@ -1,5 +1,6 @@
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use std::sync::Mutex;
 
 use cu::pre::*;
 
@@ -90,41 +91,56 @@ impl VersionCache {
     }
     /// Set the cached version to be the expected
     pub fn update(self) -> cu::Result<()> {
+        hmgr::PackageResources::record_version(self.id);
         set_cached_version(self.id, self.expected)
     }
 }
 
-fn get_cached_version(identifier: &str) -> cu::Result<Option<String>> {
+/// Get all entries in the version cache
+pub fn cached_versions() -> cu::Result<BTreeMap<String, String>> {
     let path = hmgr::paths::version_cache_json();
     if !path.exists() {
-        return Ok(None);
+        return Ok(BTreeMap::new());
     }
     let file = cu::check!(cu::fs::read_string(&path), "error reading version cache")?;
-    let map = match json::parse::<BTreeMap<String, String>>(&file) {
-        Ok(x) => x,
+    match json::parse::<BTreeMap<String, String>>(&file) {
+        Ok(x) => Ok(x),
         Err(e) => {
             cu::warn!("failed to parse version cache: {e:?}");
-            return Ok(None);
+            Ok(BTreeMap::new())
         }
-    };
-    Ok(map.get(identifier).cloned())
+    }
 }
 
-fn set_cached_version(identifier: &str, version: &str) -> cu::Result<()> {
-    let path = hmgr::paths::version_cache_json();
-    let mut map = if !path.exists() {
-        BTreeMap::new()
-    } else {
-        let file = cu::check!(cu::fs::read_string(&path), "error reading version cache")?;
-        match json::parse::<BTreeMap<String, String>>(&file) {
-            Ok(x) => x,
-            Err(e) => {
-                cu::warn!("failed to parse version cache: {e:?}");
-                Default::default()
+/// Serializes updates to the version cache file, since packages may be installed in parallel
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Set entries in the version cache. `None` removes the entry
+pub fn set_cached_versions(entries: &BTreeMap<String, Option<String>>) -> cu::Result<()> {
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut map = cached_versions()?;
+    for (identifier, version) in entries {
+        match version {
+            Some(version) => {
+                map.insert(identifier.to_string(), version.to_string());
+            }
+            None => {
+                map.remove(identifier);
             }
         }
-    };
+    }
+    cu::fs::write_json_pretty(hmgr::paths::version_cache_json(), &map)?;
+    Ok(())
+}
+
+fn get_cached_version(identifier: &str) -> cu::Result<Option<String>> {
+    Ok(cached_versions()?.remove(identifier))
+}
+
+fn set_cached_version(identifier: &str, version: &str) -> cu::Result<()> {
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut map = cached_versions()?;
     map.insert(identifier.to_string(), version.to_string());
-    cu::fs::write_json_pretty(&path, &map)?;
+    cu::fs::write_json_pretty(hmgr::paths::version_cache_json(), &map)?;
     Ok(())
 }
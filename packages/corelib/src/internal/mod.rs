@@ -1,2 +1,4 @@
 mod main_thread;
 pub use main_thread::*;
+mod singleton;
+pub use singleton::*;
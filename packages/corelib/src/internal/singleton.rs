@@ -0,0 +1,81 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_THREAD_NUMBER: AtomicU64 = AtomicU64::new(1);
+thread_local! {
+    static THREAD_NUMBER: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Get a non-zero number that identifies the current thread
+pub fn current_thread_number() -> u64 {
+    THREAD_NUMBER.with(|x| {
+        if x.get() == 0 {
+            x.set(NEXT_THREAD_NUMBER.fetch_add(1, Ordering::Relaxed));
+        }
+        x.get()
+    })
+}
+
+// Guard invariants:
+// - OWNER = number of the thread holding the guard, 0 when no guard is alive
+// - at most one guard is alive, other threads wait until it's dropped
+// - the instance is initialized when a guard is alive
+macro_rules! serialized_singleton {
+    () => {};
+    (__impl__ mod $xxx:ident, $type:ty, $init:block, $($rest:tt)* ) => {
+        mod $xxx {
+            #[allow(unused)]
+            use super::*;
+            static INSTANCE: std::sync::Mutex<Option<$type>> = std::sync::Mutex::new(None);
+            static OWNER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+            pub(crate) struct Guard(std::sync::MutexGuard<'static, Option<$type>>);
+            impl std::ops::Deref for Guard {
+                type Target = $type;
+                fn deref(&self) -> &Self::Target {
+                    // invariant: initialized when guard is alive
+                    self.0.as_ref().unwrap()
+                }
+            }
+            impl std::ops::DerefMut for Guard {
+                fn deref_mut(&mut self) -> &mut Self::Target {
+                    // invariant: initialized when guard is alive
+                    self.0.as_mut().unwrap()
+                }
+            }
+            impl Drop for Guard {
+                fn drop(&mut self) {
+                    OWNER.store(0, std::sync::atomic::Ordering::Release);
+                }
+            }
+            /// Get instance - will wait if another thread holds a guard,
+            /// and error if the current thread holds a guard
+            pub fn instance() -> cu::Result<Guard> {
+                use cu::Context as _;
+                let thread = $crate::internal::current_thread_number();
+                cu::ensure!(
+                    OWNER.load(std::sync::atomic::Ordering::Acquire) != thread,
+                    concat!("another guard of ", stringify!($xxx), " is alive")
+                )?;
+                // the instance is never left in an inconsistent state
+                let inner = INSTANCE.lock().unwrap_or_else(|e| e.into_inner());
+                OWNER.store(thread, std::sync::atomic::Ordering::Release);
+                let mut guard = Guard(inner);
+                if guard.0.is_none() {
+                    #[allow(clippy::redundant_closure_call)]
+                    let init_value: cu::Result<$type> = (|| { $init })();
+                    let init_value = init_value.context(concat!("failed to initialize singleton: ", stringify!($xxx)))?;
+                    *guard.0 = Some(init_value);
+                }
+                Ok(guard)
+            }
+        }
+        $crate::internal::serialized_singleton!($($rest)*);
+    };
+    (const $xxx:ident = $type:ident :: $init_ident:ident (); $($rest:tt)* ) => {
+        $crate::internal::serialized_singleton!(__impl__ mod $xxx, $type, { Ok($type::$init_ident()) }, $($rest)*);
+    };
+    (let $xxx:ident = $type:ident :: $init_ident:ident (); $($rest:tt)* ) => {
+        $crate::internal::serialized_singleton!(__impl__ mod $xxx, $type, { $type::$init_ident() }, $($rest)*);
+    };
+}
+pub(crate) use serialized_singleton;
@@ -1,15 +1,17 @@
-use std::io::{BufRead, Cursor, Read};
-use std::path::Path;
-#[cfg(windows)]
-use std::path::PathBuf;
+use std::io::{BufRead, Cursor, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
+use bzip2::bufread::BzDecoder;
 use cu::pre::*;
+use flate2::Compression;
 use flate2::read::GzDecoder;
-use sha2::{Digest, Sha256};
-use tar::Archive as TarArchive;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256, Sha512};
+use tar::{Archive as TarArchive, Builder as TarBuilder};
 use xz2::bufread::XzDecoder;
 use zip::ZipArchive;
+use zstd::stream::read::Decoder as ZstdDecoder;
 
 #[cfg(windows)]
 use crate::opfs;
@@ -60,6 +62,24 @@ pub fn symlink_files(paths: &[(&Path, &Path)]) -> cu::Result<()> {
     Ok(())
 }
 
+/// Create symbolic links to the absolute path of the target.
+/// `from` is where the link will be
+#[cfg(not(windows))]
+#[cu::context("failed to create symbolic links")]
+pub fn symlink_files(paths: &[(&Path, &Path)]) -> cu::Result<()> {
+    for (from, to) in paths {
+        // the existing link could be broken
+        if from.is_symlink() {
+            std::fs::remove_file(from)?;
+        } else {
+            cu::fs::remove(from)?;
+        }
+        let to_abs = to.normalize()?;
+        std::os::unix::fs::symlink(to_abs, from)?;
+    }
+    Ok(())
+}
+
 /// Create hardlinks. `from` is where the link will be and `to` is the target of the link
 #[cfg(windows)]
 #[cu::context("failed to create hard links")]
@@ -136,15 +156,49 @@ pub fn safe_remove_link(path: &Path) -> cu::Result<()> {
     cu::fs::remove(path)
 }
 
+/// Check if 2 paths point to the same file (for example, hard links of each other)
+#[cu::context("failed to compare files: '{}' and '{}'", a.display(), b.display())]
+pub fn is_same_file(a: &Path, b: &Path) -> cu::Result<bool> {
+    Ok(same_file::is_same_file(a, b)?)
+}
+
 /// Get the SHA256 checksum of a file and return it as a string
 #[cu::context("failed to hash file: '{}'", path.display())]
 pub fn file_sha256(path: &Path, bar: Option<Arc<cu::ProgressBar>>) -> cu::Result<String> {
+    let mut hasher = Sha256::new();
+    hash_file(path, bar, |x| hasher.update(x))?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Get the SHA512 checksum of a file and return it as a string
+#[cu::context("failed to hash file: '{}'", path.display())]
+pub fn file_sha512(path: &Path, bar: Option<Arc<cu::ProgressBar>>) -> cu::Result<String> {
+    let mut hasher = Sha512::new();
+    hash_file(path, bar, |x| hasher.update(x))?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Get the BLAKE3 hash of a file and return it as a string
+#[cu::context("failed to hash file: '{}'", path.display())]
+pub fn file_blake3(path: &Path, bar: Option<Arc<cu::ProgressBar>>) -> cu::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hash_file(path, bar, |x| {
+        hasher.update(x);
+    })?;
+    Ok(to_hex(hasher.finalize().as_bytes()))
+}
+
+/// Read the file and pass the content to the hasher in chunks
+fn hash_file(
+    path: &Path,
+    bar: Option<Arc<cu::ProgressBar>>,
+    mut update: impl FnMut(&[u8]),
+) -> cu::Result<()> {
     #[cfg(unix)]
     use std::os::unix::fs::MetadataExt;
     #[cfg(windows)]
     use std::os::windows::fs::MetadataExt;
 
-    let mut hasher = Sha256::new();
     let mut reader = cu::fs::reader(path)?;
 
     let metadata = path.metadata()?;
@@ -168,40 +222,119 @@ pub fn file_sha256(path: &Path, bar: Option<Arc<cu::ProgressBar>>) -> cu::Result
         if i == 0 {
             break;
         }
-        hasher.update(&buf[..i]);
+        update(&buf[..i]);
     }
     if let Some(bar) = &bar {
         cu::progress!(bar, "hashing: done");
     }
-    let result = hasher.finalize();
-    let mut out = String::with_capacity(64);
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
     let digits = b"0123456789abcdef";
-    for b in result {
+    for b in bytes {
         let c1 = digits[(b / 16) as usize] as char;
         let c2 = digits[(b % 16) as usize] as char;
         out.push(c1);
         out.push(c2);
     }
-    Ok(out)
+    out
+}
+
+/// Options for extracting an archive with [`unarchive_with`]
+#[derive(Debug, Default, Clone)]
+pub struct ExtractOptions {
+    /// Remove everything in the output directory before extracting
+    pub clean: bool,
+    /// Remove this many leading components from the path of each entry,
+    /// like `tar --strip-components`. Entries with fewer components are skipped
+    pub strip_components: usize,
+    /// Only extract entries under these paths (after stripping components).
+    /// Everything is extracted if empty
+    pub include: Vec<PathBuf>,
+    /// How to set the permissions of the extracted files
+    pub file_mode: FileMode,
+    /// Also allow symbolic links to absolute paths under this directory,
+    /// for archives created from the directory (like snapshots of HOME)
+    pub absolute_link_root: Option<PathBuf>,
+}
+
+/// Permissions of extracted files. Has no effect on Windows
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FileMode {
+    /// 0o755 if the file is executable by anyone in the archive, otherwise 0o644
+    #[default]
+    Normalize,
+    /// Keep the permission bits in the archive, without setuid, setgid and sticky bits
+    Preserve,
+}
+
+impl ExtractOptions {
+    /// Get the path relative to the output directory to extract the entry to,
+    /// or `None` if the entry should be skipped. Errors if the path
+    /// is absolute or has `..`
+    fn map_path(&self, path: &Path) -> cu::Result<Option<PathBuf>> {
+        let mut components = vec![];
+        for component in path.components() {
+            match component {
+                Component::Normal(x) => components.push(x),
+                Component::CurDir => {}
+                _ => cu::bail!("unsafe path in archive: '{}'", path.display()),
+            }
+        }
+        let path: PathBuf = components.into_iter().skip(self.strip_components).collect();
+        if path.as_os_str().is_empty() {
+            return Ok(None);
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|x| path.starts_with(x)) {
+            return Ok(None);
+        }
+        Ok(Some(path))
+    }
 }
 
 /// Extract an archive.
 ///
-/// Supports `.tar.gz`, `.tgz` and `.zip`
+/// Supports `.tar` and `.zip`, `.7z`, and `.tar` compressed with
+/// gzip (`.tgz`), xz (`.txz`), zstd (`.tzst`) or bzip2 (`.tbz2`).
+/// Other formats are extracted by spawning `7z`
 #[inline(always)]
 pub fn unarchive(
     archive_path: impl AsRef<Path>,
     out_dir: impl AsRef<Path>,
     clean: bool,
 ) -> cu::Result<()> {
-    unarchive_impl(archive_path.as_ref(), out_dir.as_ref(), clean)
+    let options = ExtractOptions {
+        clean,
+        ..Default::default()
+    };
+    unarchive_impl(archive_path.as_ref(), out_dir.as_ref(), &options)?;
+    Ok(())
 }
-fn unarchive_impl(archive_path: &Path, out_dir: &Path, clean: bool) -> cu::Result<()> {
+
+/// Extract an archive with options. See [`unarchive`] for the supported formats.
+///
+/// Returns the files (including links) written, relative to `out_dir`
+#[inline(always)]
+pub fn unarchive_with(
+    archive_path: impl AsRef<Path>,
+    out_dir: impl AsRef<Path>,
+    options: &ExtractOptions,
+) -> cu::Result<Vec<PathBuf>> {
+    unarchive_impl(archive_path.as_ref(), out_dir.as_ref(), options)
+}
+#[cu::context("failed to extract '{}'", archive_path.display())]
+fn unarchive_impl(
+    archive_path: &Path,
+    out_dir: &Path,
+    options: &ExtractOptions,
+) -> cu::Result<Vec<PathBuf>> {
     cu::trace!(
-        "extracting '{}' to '{}', clean={}",
+        "extracting '{}' to '{}', options={:?}",
         archive_path.display(),
         out_dir.display(),
-        clean
+        options
     );
     let ext = cu::check!(
         archive_path.extension(),
@@ -214,7 +347,10 @@ fn unarchive_impl(archive_path: &Path, out_dir: &Path, clean: bool) -> cu::Resul
         Tar,
         TarGz,
         TarXz,
+        TarZst,
+        TarBz2,
         Zip,
+        SevenZ,
         Use7z,
     }
     let file_size = {
@@ -229,80 +365,67 @@ fn unarchive_impl(archive_path: &Path, out_dir: &Path, clean: bool) -> cu::Resul
         let file_size = metadata.file_size();
         file_size
     };
-    let is_big_file = file_size >= 50_000_000;
+    // 7z is faster for big files, but can't strip or filter the entries
+    let is_plain = options.strip_components == 0 && options.include.is_empty();
+    let use_7z_if_big = file_size >= 50_000_000 && is_plain && cu::which("7z").is_ok();
+    // the download cache names files <stem>-<hash>.<ext>, so .tar.gz
+    // can't be detected. Compressed files are assumed to be tar
     let format = match ext.as_bytes() {
-        b"gz" => Format::TarGz, // assume tar.gz
-        b"xz" => Format::TarXz, // assume tar.xz
-        b"tgz" => Format::TarGz,
-        b"txz" => Format::TarXz,
+        b"gz" | b"tgz" => Format::TarGz,
+        b"xz" | b"txz" => Format::TarXz,
+        b"zst" | b"tzst" => Format::TarZst,
+        b"bz2" | b"tbz2" | b"tbz" => Format::TarBz2,
         b"tar" => {
-            if is_big_file {
+            if use_7z_if_big {
                 Format::Use7z
             } else {
                 Format::Tar
             }
         }
         b"zip" => {
-            if is_big_file {
+            if use_7z_if_big {
                 Format::Use7z
             } else {
                 Format::Zip
             }
         }
+        b"7z" => Format::SevenZ,
         _ => {
+            if !is_plain {
+                cu::bail!("unsupported archive extension: {ext}, cannot extract with options");
+            }
             cu::debug!("unsupported archive extension: {ext}, trying to spawn 7z to deal with it");
             Format::Use7z
         }
     };
     match format {
         Format::TarGz => {
-            let mut archive_bytes = cu::fs::reader(archive_path)?;
-            untargz_read(&mut archive_bytes, out_dir, clean)?;
+            let reader = cu::fs::reader(archive_path)?;
+            untar_read_with(GzDecoder::new(reader), out_dir, options)
         }
         Format::TarXz => {
-            let mut archive_bytes = cu::fs::reader(archive_path)?;
-            untarxz_read(&mut archive_bytes, out_dir, clean)?;
+            let reader = cu::fs::reader(archive_path)?;
+            untar_read_with(XzDecoder::new(reader), out_dir, options)
+        }
+        Format::TarZst => {
+            let reader = cu::fs::reader(archive_path)?;
+            untar_read_with(ZstdDecoder::with_buffer(reader)?, out_dir, options)
+        }
+        Format::TarBz2 => {
+            let reader = cu::fs::reader(archive_path)?;
+            untar_read_with(BzDecoder::new(reader), out_dir, options)
         }
         Format::Tar => {
-            let mut archive_bytes = cu::fs::reader(archive_path)?;
-            untar_read(&mut archive_bytes, out_dir, clean)?;
+            let reader = cu::fs::reader(archive_path)?;
+            untar_read_with(reader, out_dir, options)
         }
         Format::Zip => {
-            let archive_bytes = cu::fs::read(archive_path)?;
-            unzip_bytes(&archive_bytes, out_dir, clean)?;
-        }
-        Format::Use7z => {
-            let exe = cu::which("7z")?;
-            let command = if cfg!(windows) {
-                // on windows, spawning 7z directly is diffcult
-                // to do path escape, so we wrap it with powershell
-                let script = format!(
-                    "& {} x -y {} -o{}",
-                    quote_path(&exe)?,
-                    quote_path(archive_path)?,
-                    quote_path(out_dir)?,
-                );
-                cu::which("powershell.exe")?
-                    .command()
-                    .args(["-NoLogo", "-c", &script])
-            } else {
-                let out_dir = quote_path(out_dir)?;
-                cu::which("7z")?.command().add(cu::args![
-                    "x",
-                    "-y",
-                    archive_path,
-                    format!("-o{out_dir}")
-                ])
-            };
-            let (child, bar, _) = command
-                .stdoe(cu::pio::spinner("7z").configure_spinner(|x| x.keep(false)))
-                .stdin_null()
-                .spawn()?;
-            child.wait_nz()?;
-            bar.done();
+            let reader = cu::fs::reader(archive_path)?;
+            unzip_read_with(reader, out_dir, options)
         }
+        Format::SevenZ => un7z_with(archive_path, out_dir, options),
+        Format::Use7z => un7z_spawn(archive_path, out_dir, options),
     }
-    Ok(())
 }
 
 #[cu::context("failed to unpack targz bytes")]
@@ -310,6 +433,18 @@ pub fn untargz_read(archive_bytes: impl BufRead, out_dir: &Path, clean: bool) ->
     untar_read(GzDecoder::new(archive_bytes), out_dir, clean)
 }
 
+/// Extract `.tar.gz` bytes with options.
+///
+/// Returns the files (including links) written, relative to `out_dir`
+#[cu::context("failed to unpack targz bytes")]
+pub fn untargz_read_with(
+    archive_bytes: impl BufRead,
+    out_dir: &Path,
+    options: &ExtractOptions,
+) -> cu::Result<Vec<PathBuf>> {
+    untar_read_with(GzDecoder::new(archive_bytes), out_dir, options)
+}
+
 #[cu::context("failed to unpack tarxz bytes")]
 pub fn untarxz_read(archive_bytes: impl BufRead, out_dir: &Path, clean: bool) -> cu::Result<()> {
     untar_read(XzDecoder::new(archive_bytes), out_dir, clean)
@@ -317,21 +452,443 @@ pub fn untarxz_read(archive_bytes: impl BufRead, out_dir: &Path, clean: bool) ->
 
 #[cu::context("failed to unpack tar bytes")]
 pub fn untar_read(archive_bytes: impl Read, out_dir: &Path, clean: bool) -> cu::Result<()> {
-    if clean {
-        cu::fs::make_dir_empty(out_dir)?;
-    }
-    let mut archive = TarArchive::new(archive_bytes);
-    archive.unpack(out_dir)?;
+    let options = ExtractOptions {
+        clean,
+        ..Default::default()
+    };
+    untar_read_with(archive_bytes, out_dir, &options)?;
     Ok(())
 }
 
+fn untar_read_with(
+    archive_bytes: impl Read,
+    out_dir: &Path,
+    options: &ExtractOptions,
+) -> cu::Result<Vec<PathBuf>> {
+    let mut extractor = Extractor::new(out_dir, options)?;
+    let mut archive = TarArchive::new(archive_bytes);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let Some((rel_path, out_path)) = extractor.prepare(&path)? else {
+            continue;
+        };
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            cu::fs::make_dir(&out_path)?;
+            continue;
+        }
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = cu::check!(
+                entry.link_name()?,
+                "missing link target of '{}'",
+                path.display()
+            )?;
+            if entry_type.is_symlink() {
+                extractor.symlink(rel_path, &out_path, &target)?;
+            } else {
+                extractor.hard_link(rel_path, &out_path, &target)?;
+            }
+            continue;
+        }
+        if !entry_type.is_file() && !entry_type.is_contiguous() && !entry_type.is_gnu_sparse() {
+            cu::debug!("skipping '{}' of type {entry_type:?}", path.display());
+            continue;
+        }
+        entry.unpack(&out_path)?;
+        let mode = entry.header().mode().ok();
+        extractor.file(rel_path, &out_path, mode)?;
+    }
+    Ok(extractor.finish())
+}
+
 #[cu::context("failed to unpack zip bytes")]
 pub fn unzip_bytes(archive_bytes: &[u8], out_dir: &Path, clean: bool) -> cu::Result<()> {
-    if clean {
-        cu::fs::make_dir_empty(out_dir)?;
+    let options = ExtractOptions {
+        clean,
+        ..Default::default()
+    };
+    unzip_read_with(Cursor::new(archive_bytes), out_dir, &options)?;
+    Ok(())
+}
+
+fn unzip_read_with(
+    archive_bytes: impl Read + Seek,
+    out_dir: &Path,
+    options: &ExtractOptions,
+) -> cu::Result<Vec<PathBuf>> {
+    let mut extractor = Extractor::new(out_dir, options)?;
+    let mut archive = ZipArchive::new(archive_bytes)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.enclosed_name().is_none() {
+            cu::bail!("unsafe path in archive: '{}'", file.name());
+        }
+        // enclosed_name() drops the root of absolute paths,
+        // so the name is checked again when mapped
+        let path = PathBuf::from(file.name());
+        let Some((rel_path, out_path)) = extractor.prepare(&path)? else {
+            continue;
+        };
+        if file.is_dir() {
+            cu::fs::make_dir(&out_path)?;
+            continue;
+        }
+        if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            extractor.symlink(rel_path, &out_path, Path::new(&target))?;
+            continue;
+        }
+        let mut writer = cu::fs::buf_writer(&out_path)?;
+        std::io::copy(&mut file, &mut writer)?;
+        writer.flush()?;
+        // the executable bits are in the external attributes,
+        // if the archive is created on unix
+        extractor.file(rel_path, &out_path, file.unix_mode())?;
+    }
+    Ok(extractor.finish())
+}
+
+fn un7z_with(
+    archive_path: &Path,
+    out_dir: &Path,
+    options: &ExtractOptions,
+) -> cu::Result<Vec<PathBuf>> {
+    let mut extractor = Extractor::new(out_dir, options)?;
+    // errors from the callback can't be returned through sevenz_rust
+    let mut error = None;
+    let result =
+        sevenz_rust::decompress_file_with_extract_fn(archive_path, out_dir, |entry, reader, _| {
+            match un7z_entry(&mut extractor, entry, reader) {
+                Ok(()) => Ok(true),
+                Err(e) => {
+                    error = Some(e);
+                    Err(sevenz_rust::Error::other("extraction aborted"))
+                }
+            }
+        });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    result?;
+    Ok(extractor.finish())
+}
+
+fn un7z_entry(
+    extractor: &mut Extractor,
+    entry: &sevenz_rust::SevenZArchiveEntry,
+    reader: &mut dyn Read,
+) -> cu::Result<()> {
+    let Some((rel_path, out_path)) = extractor.prepare(Path::new(entry.name()))? else {
+        // entries in a solid block are read in order, so the content must be consumed
+        std::io::copy(reader, &mut std::io::sink())?;
+        return Ok(());
+    };
+    if entry.is_directory() {
+        cu::fs::make_dir(&out_path)?;
+        return Ok(());
+    }
+    // archives created on unix have the mode in the high bits, with this flag
+    let mode = if entry.has_windows_attributes && entry.windows_attributes & 0x8000 != 0 {
+        Some(entry.windows_attributes >> 16)
+    } else {
+        None
+    };
+    if mode.is_some_and(|x| x & 0o170000 == 0o120000) {
+        let mut target = String::new();
+        reader.read_to_string(&mut target)?;
+        return extractor.symlink(rel_path, &out_path, Path::new(&target));
+    }
+    let mut writer = cu::fs::buf_writer(&out_path)?;
+    std::io::copy(reader, &mut writer)?;
+    writer.flush()?;
+    extractor.file(rel_path, &out_path, mode)
+}
+
+/// Extract by spawning `7z` into a staging directory, then move the files
+/// to the output directory with the same checks as other formats
+fn un7z_spawn(
+    archive_path: &Path,
+    out_dir: &Path,
+    options: &ExtractOptions,
+) -> cu::Result<Vec<PathBuf>> {
+    let mut extractor = Extractor::new(out_dir, options)?;
+    // inside the output, so the files can be moved instead of copied
+    let staging = out_dir.join(".shaft-7z-staging");
+    cu::fs::make_dir_empty(&staging)?;
+    let exe = cu::which("7z")?;
+    let command = if cfg!(windows) {
+        // on windows, spawning 7z directly is diffcult
+        // to do path escape, so we wrap it with powershell
+        let script = format!(
+            "& {} x -y {} -o{}",
+            quote_path(&exe)?,
+            quote_path(archive_path)?,
+            quote_path(&staging)?,
+        );
+        cu::which("powershell.exe")?
+            .command()
+            .args(["-NoLogo", "-c", &script])
+    } else {
+        let staging = quote_path(&staging)?;
+        exe.command()
+            .add(cu::args!["x", "-y", archive_path, format!("-o{staging}")])
+    };
+    let (child, bar, _) = command
+        .stdoe(cu::pio::spinner("7z").configure_spinner(|x| x.keep(false)))
+        .stdin_null()
+        .spawn()?;
+    child.wait_nz()?;
+    bar.done();
+    let result = move_staged(&mut extractor, &staging, &staging);
+    cu::fs::rec_remove(&staging)?;
+    result?;
+    Ok(extractor.finish())
+}
+
+fn move_staged(extractor: &mut Extractor, staging: &Path, dir: &Path) -> cu::Result<()> {
+    for entry in cu::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let rel = path.strip_prefix(staging)?;
+        let file_type = path.symlink_metadata()?.file_type();
+        let target = extractor.prepare(rel)?;
+        if file_type.is_dir() {
+            if let Some((_, out_path)) = target {
+                cu::fs::make_dir(&out_path)?;
+            }
+            move_staged(extractor, staging, &path)?;
+            continue;
+        }
+        let Some((rel_path, out_path)) = target else {
+            continue;
+        };
+        if file_type.is_symlink() {
+            let link_target = std::fs::read_link(&path)?;
+            extractor.symlink(rel_path, &out_path, &link_target)?;
+            continue;
+        }
+        let mode = current_mode(&path)?;
+        std::fs::rename(&path, &out_path)?;
+        extractor.file(rel_path, &out_path, mode)?;
+    }
+    Ok(())
+}
+
+/// Writes entries of an archive to the output directory, making sure nothing
+/// is written outside of it, and records the files written
+struct Extractor<'a> {
+    options: &'a ExtractOptions,
+    /// The output directory with links resolved
+    out_dir: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(out_dir: &Path, options: &'a ExtractOptions) -> cu::Result<Self> {
+        if options.clean {
+            cu::fs::make_dir_empty(out_dir)?;
+        } else {
+            cu::fs::make_dir(out_dir)?;
+        }
+        Ok(Self {
+            options,
+            out_dir: std::fs::canonicalize(out_dir)?,
+            files: vec![],
+        })
+    }
+
+    /// Map the path of the entry, and create its parent directory.
+    /// Returns the path relative to the output directory and the path to write to,
+    /// or `None` if the entry is skipped
+    fn prepare(&self, path: &Path) -> cu::Result<Option<(PathBuf, PathBuf)>> {
+        let Some(rel_path) = self.options.map_path(path)? else {
+            cu::trace!("skipping '{}'", path.display());
+            return Ok(None);
+        };
+        let out_path = self.out_dir.join(&rel_path);
+        if let Some(parent) = out_path.parent() {
+            cu::fs::make_dir(parent)?;
+            // a link extracted earlier could be in the path
+            self.depth_of(parent)?;
+        }
+        // don't write through an existing link
+        if out_path.is_symlink() {
+            cu::fs::remove(&out_path)?;
+        }
+        Ok(Some((rel_path, out_path)))
+    }
+
+    /// Get how deep the path is in the output directory with links resolved,
+    /// or error if it's outside
+    fn depth_of(&self, path: &Path) -> cu::Result<usize> {
+        let real = std::fs::canonicalize(path)?;
+        match real.strip_prefix(&self.out_dir) {
+            Ok(rel) => Ok(rel.components().count()),
+            Err(_) => cu::bail!("'{}' is outside of the output directory", path.display()),
+        }
+    }
+
+    fn file(&mut self, rel_path: PathBuf, out_path: &Path, mode: Option<u32>) -> cu::Result<()> {
+        set_file_mode(out_path, self.options.file_mode, mode)?;
+        self.files.push(rel_path);
+        Ok(())
+    }
+
+    fn symlink(&mut self, rel_path: PathBuf, out_path: &Path, target: &Path) -> cu::Result<()> {
+        let depth = match out_path.parent() {
+            Some(parent) => self.depth_of(parent)?,
+            None => 0,
+        };
+        if !is_enclosed_link(depth, target) && !self.is_allowed_absolute_link(target) {
+            cu::bail!(
+                "symbolic link '{}' -> '{}' points outside of the output directory",
+                rel_path.display(),
+                target.display()
+            );
+        }
+        if create_symlink(target, out_path)? {
+            self.files.push(rel_path);
+        }
+        Ok(())
+    }
+
+    /// Check if the target is an absolute path under the allowed root
+    fn is_allowed_absolute_link(&self, target: &Path) -> bool {
+        let Some(root) = &self.options.absolute_link_root else {
+            return false;
+        };
+        target.is_absolute()
+            && !target.components().any(|x| x == Component::ParentDir)
+            && target.starts_with(root)
+    }
+
+    /// Create a hard link to another entry in the archive
+    fn hard_link(&mut self, rel_path: PathBuf, out_path: &Path, target: &Path) -> cu::Result<()> {
+        let target = cu::check!(
+            self.options.map_path(target)?,
+            "target of hard link '{}' is not extracted",
+            rel_path.display()
+        )?;
+        let target = self.out_dir.join(target);
+        self.depth_of(&target)?;
+        cu::fs::remove(out_path)?;
+        std::fs::hard_link(target, out_path)?;
+        self.files.push(rel_path);
+        Ok(())
+    }
+
+    /// Get the files written
+    fn finish(mut self) -> Vec<PathBuf> {
+        self.files.sort();
+        self.files.dedup();
+        self.files
+    }
+}
+
+/// Check if the target of a symbolic link stays inside the output directory.
+/// `depth` is how deep the directory of the link is.
+///
+/// `..` is only allowed at the start of the target, since anything after
+/// a normal component could be another link
+fn is_enclosed_link(depth: usize, target: &Path) -> bool {
+    let mut depth = depth;
+    let mut descended = false;
+    for component in target.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(_) => {
+                depth += 1;
+                descended = true;
+            }
+            Component::ParentDir => {
+                if descended || depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            // absolute
+            _ => return false,
+        }
     }
-    let mut archive = ZipArchive::new(Cursor::new(archive_bytes))?;
-    archive.extract(out_dir)?; // to be consistent with 7z, we do not unwrap root dir
+    true
+}
+
+/// Create a symbolic link, returns if it's created
+#[cfg(windows)]
+fn create_symlink(target: &Path, path: &Path) -> cu::Result<bool> {
+    // creating symlinks requires sudo on windows
+    cu::warn!(
+        "skipping symbolic link '{}' -> '{}' in archive",
+        path.display(),
+        target.display()
+    );
+    Ok(false)
+}
+
+/// Create a symbolic link, returns if it's created
+#[cfg(not(windows))]
+fn create_symlink(target: &Path, path: &Path) -> cu::Result<bool> {
+    std::os::unix::fs::symlink(target, path)?;
+    Ok(true)
+}
+
+#[cfg(windows)]
+fn set_file_mode(_: &Path, _: FileMode, _: Option<u32>) -> cu::Result<()> {
+    Ok(())
+}
+
+/// Set the permissions of an extracted file, with the mode from the archive if any
+#[cfg(not(windows))]
+fn set_file_mode(path: &Path, policy: FileMode, mode: Option<u32>) -> cu::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let Some(mode) = mode else {
+        return Ok(());
+    };
+    let mode = match policy {
+        FileMode::Normalize => {
+            if mode & 0o111 != 0 {
+                0o755
+            } else {
+                0o644
+            }
+        }
+        FileMode::Preserve => mode & 0o777,
+    };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn current_mode(_: &Path) -> cu::Result<Option<u32>> {
+    Ok(None)
+}
+
+#[cfg(not(windows))]
+fn current_mode(path: &Path) -> cu::Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(Some(path.metadata()?.permissions().mode()))
+}
+
+/// Create a `.tar.gz` archive from files on disk.
+///
+/// Each entry is the path in the archive and the path of the file to add.
+/// Directories are added recursively
+#[cu::context("failed to create archive '{}'", out_path.display())]
+pub fn targz_files(out_path: &Path, files: &[(String, PathBuf)]) -> cu::Result<()> {
+    let writer = cu::fs::buf_writer(out_path)?;
+    let mut builder = TarBuilder::new(GzEncoder::new(writer, Compression::default()));
+    builder.follow_symlinks(false);
+    for (name, path) in files {
+        let result = if path.is_dir() {
+            builder.append_dir_all(name, path)
+        } else {
+            builder.append_path_with_name(path, name)
+        };
+        cu::check!(result, "failed to add '{}' to archive", path.display())?;
+    }
+    let mut writer = builder.into_inner()?.finish()?;
+    writer.flush()?;
     Ok(())
 }
 
@@ -344,6 +901,33 @@ pub fn ungz_bytes(bytes: &[u8], out_path: &Path) -> cu::Result<()> {
     cu::fs::write(out_path, buf)
 }
 
+/// Decompress a single compressed file (not an archive) into a file.
+///
+/// Supports `.gz`, `.xz`, `.zst` and `.bz2`
+#[inline(always)]
+pub fn decompress_file(path: impl AsRef<Path>, out_path: impl AsRef<Path>) -> cu::Result<()> {
+    decompress_file_impl(path.as_ref(), out_path.as_ref())
+}
+#[cu::context("failed to decompress '{}'", path.display())]
+fn decompress_file_impl(path: &Path, out_path: &Path) -> cu::Result<()> {
+    let ext = path
+        .extension()
+        .map(|x| x.to_ascii_lowercase())
+        .unwrap_or_default();
+    let reader = cu::fs::reader(path)?;
+    let mut decoder: Box<dyn Read> = match ext.as_encoded_bytes() {
+        b"gz" => Box::new(GzDecoder::new(reader)),
+        b"xz" => Box::new(XzDecoder::new(reader)),
+        b"zst" => Box::new(ZstdDecoder::with_buffer(reader)?),
+        b"bz2" => Box::new(BzDecoder::new(reader)),
+        _ => cu::bail!("unsupported compression: '{}'", path.display()),
+    };
+    let mut writer = cu::fs::buf_writer(out_path)?;
+    std::io::copy(&mut decoder, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
 /// Ensure nothing weird happens when the path is quoted
 #[inline(always)]
 pub fn quote_path(path: impl AsRef<Path>) -> cu::Result<String> {
@@ -381,3 +965,333 @@ fn find_in_wingit_impl(path: &Path) -> cu::Result<PathBuf> {
     cu::trace!("found mingw64: '{}'", mingw64_path.display());
     git_path.join(path).normalize_executable()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extract_options_map_path() {
+        let options = ExtractOptions {
+            strip_components: 1,
+            include: vec![PathBuf::from("bin")],
+            ..Default::default()
+        };
+        let map = |x: &str| options.map_path(Path::new(x)).unwrap();
+        assert_eq!(map("nvim/bin/nvim"), Some(PathBuf::from("bin/nvim")));
+        assert_eq!(map("./nvim/bin"), Some(PathBuf::from("bin")));
+        assert_eq!(map("nvim/lib/nvim"), None);
+        assert_eq!(map("nvim"), None);
+        assert_eq!(map("nvim/binary"), None);
+        assert!(options.map_path(Path::new("../nvim/bin/nvim")).is_err());
+        assert!(options.map_path(Path::new("nvim/../../bin/nvim")).is_err());
+    }
+
+    #[test]
+    fn enclosed_link() {
+        assert!(is_enclosed_link(0, Path::new("bin/nvim")));
+        assert!(is_enclosed_link(1, Path::new("../lib/libfoo.so")));
+        assert!(is_enclosed_link(1, Path::new("./foo")));
+        assert!(!is_enclosed_link(0, Path::new("../foo")));
+        assert!(!is_enclosed_link(2, Path::new("../../../foo")));
+        assert!(!is_enclosed_link(1, Path::new("foo/../..")));
+        assert!(!is_enclosed_link(1, Path::new("/etc/passwd")));
+    }
+
+    /// Make an empty directory for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shaft-extract-test-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Files in the test archives, only `pkg/bin/tool` is extracted with [`strip_bin`]
+    const FILES: &[(&str, &str)] = &[
+        ("pkg/README", "readme"),
+        ("pkg/bin/tool", "tool"),
+        ("pkg/share/doc", "doc"),
+    ];
+
+    fn strip_bin() -> ExtractOptions {
+        ExtractOptions {
+            strip_components: 1,
+            include: vec![PathBuf::from("bin")],
+            ..Default::default()
+        }
+    }
+
+    fn tar_bytes() -> Vec<u8> {
+        let mut builder = TarBuilder::new(Vec::new());
+        for (path, content) in FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn assert_strip_bin(dir: &Path, files: Vec<PathBuf>) {
+        let out_dir = dir.join("out");
+        assert_eq!(files, vec![PathBuf::from("bin/tool")]);
+        assert_eq!(
+            std::fs::read_to_string(out_dir.join("bin/tool")).unwrap(),
+            "tool"
+        );
+        assert!(!out_dir.join("README").exists());
+        assert!(!out_dir.join("share").exists());
+        assert!(!out_dir.join("pkg").exists());
+    }
+
+    #[test]
+    fn extract_tar_zst() {
+        let dir = test_dir("tar-zst");
+        let archive = dir.join("x.tar.zst");
+        std::fs::write(&archive, zstd::encode_all(&tar_bytes()[..], 0).unwrap()).unwrap();
+        let files = unarchive_with(&archive, dir.join("out"), &strip_bin()).unwrap();
+        assert_strip_bin(&dir, files);
+    }
+
+    #[test]
+    fn extract_tar_bz2() {
+        let dir = test_dir("tar-bz2");
+        let archive = dir.join("x.tar.bz2");
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(&tar_bytes()).unwrap();
+        std::fs::write(&archive, encoder.finish().unwrap()).unwrap();
+        let files = unarchive_with(&archive, dir.join("out"), &strip_bin()).unwrap();
+        assert_strip_bin(&dir, files);
+    }
+
+    #[test]
+    fn extract_7z_solid() {
+        let dir = test_dir("7z");
+        let src = dir.join("src");
+        for (path, content) in FILES {
+            let path = src.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        // all files are in one solid block, so `README` before `bin/tool`
+        // is skipped but must still be read
+        let archive = dir.join("x.7z");
+        sevenz_rust::compress_to_path(&src, &archive).unwrap();
+        let files = unarchive_with(&archive, dir.join("out"), &strip_bin()).unwrap();
+        assert_strip_bin(&dir, files);
+    }
+
+    #[test]
+    fn decompress_single_file() {
+        let dir = test_dir("single");
+        let content = "hello ".repeat(100);
+
+        let zst = dir.join("x.zst");
+        std::fs::write(&zst, zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+        decompress_file(&zst, dir.join("zst-out")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("zst-out")).unwrap(),
+            content
+        );
+
+        let bz2 = dir.join("x.bz2");
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        std::fs::write(&bz2, encoder.finish().unwrap()).unwrap();
+        decompress_file(&bz2, dir.join("bz2-out")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("bz2-out")).unwrap(),
+            content
+        );
+    }
+
+    /// List everything in the test directory outside of `out`, without following links
+    fn list_outside(dir: &Path) -> Vec<PathBuf> {
+        fn walk(dir: &Path, root: &Path, paths: &mut Vec<PathBuf>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path == root.join("out") {
+                    continue;
+                }
+                paths.push(path.strip_prefix(root).unwrap().to_path_buf());
+                if std::fs::symlink_metadata(&path).unwrap().is_dir() {
+                    walk(&path, root, paths);
+                }
+            }
+        }
+        let mut paths = vec![];
+        walk(dir, dir, &mut paths);
+        paths.sort();
+        paths
+    }
+
+    /// Entry in a crafted tar archive
+    enum TarEntry<'a> {
+        File(&'a str),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    /// Make a tar archive with the names written directly to the headers,
+    /// since the builder refuses unsafe paths
+    fn crafted_tar(entries: &[TarEntry<'_>]) -> Vec<u8> {
+        let mut builder = TarBuilder::new(Vec::new());
+        for entry in entries {
+            let mut header = tar::Header::new_old();
+            let (name, link, entry_type) = match entry {
+                TarEntry::File(name) => (name, "", tar::EntryType::Regular),
+                TarEntry::Symlink(name, link) => (name, *link, tar::EntryType::Symlink),
+                TarEntry::HardLink(name, link) => (name, *link, tar::EntryType::Link),
+            };
+            let old = header.as_old_mut();
+            old.name[..name.len()].copy_from_slice(name.as_bytes());
+            old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_mode(0o644);
+            let data: &[u8] = if entry_type.is_file() { b"evil" } else { b"" };
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// Extract the tar and check that it fails without writing outside of `out`
+    fn assert_tar_refused(name: &str, entries: &[TarEntry<'_>], options: &ExtractOptions) {
+        let dir = test_dir(name);
+        let bytes = crafted_tar(entries);
+        let before = list_outside(&dir);
+        let result = untar_read_with(&bytes[..], &dir.join("out"), options);
+        assert!(result.is_err(), "{name} should be refused");
+        assert_eq!(list_outside(&dir), before);
+    }
+
+    fn assert_zip_refused(name: &str, bytes: Vec<u8>, options: &ExtractOptions) {
+        let dir = test_dir(name);
+        let before = list_outside(&dir);
+        let result = unzip_read_with(Cursor::new(bytes), &dir.join("out"), options);
+        assert!(result.is_err(), "{name} should be refused");
+        assert_eq!(list_outside(&dir), before);
+    }
+
+    /// Allow absolute links to the test directory, so writing through the link is checked.
+    /// Returns the options and the target outside of `out`
+    #[cfg(unix)]
+    fn allow_links_to(dir: &Path) -> (ExtractOptions, PathBuf) {
+        let root = std::fs::canonicalize(dir).unwrap();
+        let outside = root.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        let options = ExtractOptions {
+            absolute_link_root: Some(root),
+            ..Default::default()
+        };
+        (options, outside)
+    }
+
+    #[test]
+    fn tar_unsafe_entries() {
+        let options = ExtractOptions::default();
+        assert_tar_refused("tar-parent", &[TarEntry::File("../evil")], &options);
+        let absolute = std::env::temp_dir().join("shaft-extract-test-tar-absolute/evil");
+        assert_tar_refused(
+            "tar-absolute",
+            &[TarEntry::File(absolute.to_str().unwrap())],
+            &options,
+        );
+        assert_tar_refused(
+            "tar-symlink",
+            &[TarEntry::Symlink("link", "../outside")],
+            &options,
+        );
+        assert_tar_refused(
+            "tar-hardlink",
+            &[TarEntry::HardLink("link", "../secret")],
+            &options,
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tar_write_through_symlink() {
+        let dir = test_dir("tar-through-link");
+        let (options, outside) = allow_links_to(&dir);
+        let bytes = crafted_tar(&[
+            TarEntry::Symlink("link", outside.to_str().unwrap()),
+            TarEntry::File("link/evil"),
+        ]);
+        let before = list_outside(&dir);
+        let result = untar_read_with(&bytes[..], &dir.join("out"), &options);
+        assert!(result.is_err());
+        assert_eq!(list_outside(&dir), before);
+    }
+
+    #[test]
+    fn zip_unsafe_entries() {
+        let options = ExtractOptions::default();
+        let zip_file = |name: &str| {
+            let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+            writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(b"evil").unwrap();
+            writer.finish().unwrap().into_inner()
+        };
+        assert_zip_refused("zip-parent", zip_file("../evil"), &options);
+        assert_zip_refused("zip-absolute", zip_file("/evil"), &options);
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_symlink(
+                "link",
+                "../outside",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert_zip_refused("zip-symlink", bytes, &options);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zip_write_through_symlink() {
+        let dir = test_dir("zip-through-link");
+        let (options, outside) = allow_links_to(&dir);
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let zip_options = zip::write::SimpleFileOptions::default();
+        writer
+            .add_symlink("link", outside.to_str().unwrap(), zip_options)
+            .unwrap();
+        writer.start_file("link/evil", zip_options).unwrap();
+        writer.write_all(b"evil").unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let before = list_outside(&dir);
+        let result = unzip_read_with(Cursor::new(bytes), &dir.join("out"), &options);
+        assert!(result.is_err());
+        assert_eq!(list_outside(&dir), before);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn absolute_link_in_root() {
+        let dir = test_dir("absolute-link");
+        let root = std::fs::canonicalize(&dir).unwrap();
+        let options = ExtractOptions {
+            absolute_link_root: Some(root.clone()),
+            ..Default::default()
+        };
+        let target = root.join("items/tool");
+        let other = root.join("../tool");
+        let bytes = crafted_tar(&[TarEntry::Symlink("link", target.to_str().unwrap())]);
+        let files = untar_read_with(&bytes[..], &dir.join("out"), &options).unwrap();
+        assert_eq!(files, vec![PathBuf::from("link")]);
+        assert_eq!(std::fs::read_link(dir.join("out/link")).unwrap(), target);
+
+        let bytes = crafted_tar(&[TarEntry::Symlink("link", other.to_str().unwrap())]);
+        assert!(untar_read_with(&bytes[..], &dir.join("out"), &options).is_err());
+        let bytes = crafted_tar(&[TarEntry::Symlink("link", "/etc/passwd")]);
+        assert!(untar_read_with(&bytes[..], &dir.join("out"), &options).is_err());
+    }
+}
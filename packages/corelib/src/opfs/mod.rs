@@ -6,3 +6,5 @@ mod sysinfo;
 pub use sysinfo::*;
 mod fs_util;
 pub use fs_util::*;
+mod time;
+pub use time::*;
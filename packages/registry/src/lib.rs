@@ -11,6 +11,13 @@ pub use _gen::{BinId, PkgId};
 mod util;
 pub use util::*;
 
+/// Check if the digest (hex string of any algorithm) is of a download in the current registry
+pub fn is_current_digest(hex: &str) -> bool {
+    metadata::ALL_DIGEST
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(hex))
+}
+
 pub(crate) mod pre {
     pub(crate) use crate::macros::*;
     #[allow(unused)]
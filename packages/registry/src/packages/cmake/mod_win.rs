@@ -51,11 +51,14 @@ pub fn install(ctx: &Context) -> cu::Result<()> {
             .keep(true)
             .parent(ctx.bar())
             .spawn();
-        let temp_extract_dir = hmgr::paths::temp_dir("cmake-extract");
         let cmake_zip = hmgr::paths::download("cmake.zip", cmake_url());
-        opfs::unarchive(&cmake_zip, &temp_extract_dir, true)?;
-        let cmake_dir_from = temp_extract_dir.join(release_name());
-        cu::fs::rename(cmake_dir_from, ctx.install_dir())?;
+        // everything is in the release_name() directory in the archive
+        let options = opfs::ExtractOptions {
+            clean: true,
+            strip_components: 1,
+            ..Default::default()
+        };
+        opfs::unarchive_with(&cmake_zip, ctx.install_dir(), &options)?;
         bar.done();
     }
     Ok(())
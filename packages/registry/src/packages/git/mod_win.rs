@@ -9,6 +9,9 @@ version_cache!(static ALIAS_VERSION = metadata::git::ALIAS_VERSION);
 register_binaries!("git", "scalar", "bash");
 
 pub fn verify(_: &Context) -> cu::Result<Verified> {
+    crate::VerifyReport::record_external(crate::ExternalPackage::Winget(
+        "Microsoft.Git".to_string(),
+    ));
     check_in_path!("git");
     let version = command_output!("git", ["--version"]);
     if !version.contains("vfs") {
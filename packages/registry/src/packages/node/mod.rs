@@ -15,12 +15,9 @@ pub fn verify(_: &Context) -> cu::Result<Verified> {
 }
 
 pub fn download(ctx: &Context) -> cu::Result<()> {
-    hmgr::download_file(
-        volta_file_name(),
-        volta_url()?,
-        metadata::volta::SHA,
-        ctx.bar(),
-    )?;
+    let url = volta_url()?;
+    let integrity = metadata::volta::integrity(&url);
+    hmgr::download_file(volta_file_name(), url, integrity, ctx.bar())?;
     Ok(())
 }
 pub fn install(ctx: &Context) -> cu::Result<()> {
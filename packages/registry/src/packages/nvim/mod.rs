@@ -37,18 +37,15 @@ pub fn download(ctx: &Context) -> cu::Result<()> {
 
 pub fn install(ctx: &Context) -> cu::Result<()> {
     opfs::ensure_terminated(bin_name!("nvim"))?;
-    let file_name = nvim_file_name()?;
-    let file_stem = cu::check!(
-        Path::new(file_name).file_stem(),
-        "failed to get nvim file stem"
-    )?;
-    let file_stem = Path::new(file_stem).file_stem().unwrap_or(file_stem); // for .tar.gz
-    let archive_path = hmgr::paths::download(file_name, nvim_url()?);
-    let temp_dir = hmgr::paths::temp_dir("nvim-extract");
-    opfs::unarchive(archive_path, &temp_dir, true)?;
+    let archive_path = hmgr::paths::download(nvim_file_name()?, nvim_url()?);
     ctx.move_install_to_old_if_exists()?;
-    let install_dir = ctx.install_dir();
-    cu::fs::rename(temp_dir.join(file_stem), install_dir)?;
+    // everything is in a directory named after the file in the archive
+    let options = opfs::ExtractOptions {
+        clean: true,
+        strip_components: 1,
+        ..Default::default()
+    };
+    opfs::unarchive_with(archive_path, ctx.install_dir(), &options)?;
     Ok(())
 }
 
@@ -67,9 +67,11 @@ pub fn install(ctx: &Context) -> cu::Result<()> {
     cu::fs::make_dir(&install_dir)?;
 
     let task_tgz = hmgr::paths::download("task.tgz", task_url());
-    let task_temp = hmgr::paths::temp_dir("task-unarchive");
-    opfs::unarchive(task_tgz, &task_temp, true)?;
-    cu::fs::copy(task_temp.join("task"), install_dir.join("task"))?;
+    let options = opfs::ExtractOptions {
+        include: vec!["task".into()],
+        ..Default::default()
+    };
+    opfs::unarchive_with(task_tgz, &install_dir, &options)?;
 
     epkg::pacman::install("perl", ctx.bar_ref())?;
     epkg::pacman::install("curl", ctx.bar_ref())?;
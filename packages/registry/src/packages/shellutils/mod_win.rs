@@ -98,10 +98,11 @@ pub fn install(ctx: &Context) -> cu::Result<()> {
     cu::fs::copy(jq_exe, jq_target)?;
 
     let task_zip = hmgr::paths::download("task.zip", task_url());
-    let temp_dir = hmgr::paths::temp_dir("task-zip");
-    opfs::unarchive(task_zip, &temp_dir, false)?;
-    let task_exe = temp_dir.join(bin_name!("task"));
-    cu::fs::copy(task_exe, install_dir.join(bin_name!("task")))?;
+    let options = opfs::ExtractOptions {
+        include: vec![bin_name!("task").into()],
+        ..Default::default()
+    };
+    opfs::unarchive_with(task_zip, &install_dir, &options)?;
 
     epkg::cargo::binstall("bat", ctx.bar_ref())?;
     epkg::cargo::binstall("du-dust", ctx.bar_ref())?;
@@ -11,12 +11,9 @@ pub fn verify(_: &Context) -> cu::Result<Verified> {
 }
 pub fn download(ctx: &Context) -> cu::Result<()> {
     let file_name = tree_sitter_base_name()?;
-    hmgr::download_file(
-        format!("{file_name}.gz"),
-        tree_sitter_url()?,
-        metadata::tree_sitter::SHA,
-        ctx.bar(),
-    )?;
+    let url = tree_sitter_url()?;
+    let integrity = metadata::tree_sitter::integrity(&url);
+    hmgr::download_file(format!("{file_name}.gz"), url, integrity, ctx.bar())?;
     Ok(())
 }
 
@@ -41,9 +38,8 @@ fn tree_sitter_base_name() -> cu::Result<&'static str> {
 pub fn install(ctx: &Context) -> cu::Result<()> {
     let file_name = tree_sitter_base_name()?;
     let tree_sitter_download = hmgr::paths::download(format!("{file_name}.gz"), tree_sitter_url()?);
-    let tree_sitter_bytes = cu::fs::read(tree_sitter_download)?;
     let tree_sitter_binary_path = ctx.install_dir().join(bin_name!("tree-sitter"));
-    opfs::ungz_bytes(&tree_sitter_bytes, &tree_sitter_binary_path)?;
+    opfs::decompress_file(tree_sitter_download, &tree_sitter_binary_path)?;
     opfs::set_executable(&tree_sitter_binary_path)?;
     Ok(())
 }
@@ -5,6 +5,7 @@ use std::sync::Arc;
 use corelib::hmgr::config::ConfigDef;
 use corelib::hmgr::{self, Item, ItemMgr};
 use cu::pre::*;
+use enum_map::EnumMap;
 use enumset::EnumSet;
 
 use crate::PkgId;
@@ -16,8 +17,12 @@ pub struct Context {
     pub stage: cu::Atomic<u8, Stage>,
     /// Shim config
     items: RefCell<ItemMgr>,
+    /// If the context is forked, which does not have the config items
+    forked: bool,
     bar: Option<Arc<cu::ProgressBar>>,
     installed: EnumSet<PkgId>,
+    /// Values to apply on top of the config file when loading the config
+    config_overrides: EnumMap<PkgId, Option<toml::Table>>,
 }
 impl Context {
     pub fn new(items: ItemMgr) -> Self {
@@ -25,8 +30,23 @@ impl Context {
             pkg: PkgId::Core,
             stage: cu::Atomic::new_u8(Stage::Verify.into()),
             items: RefCell::new(items),
+            forked: false,
             bar: None,
             installed: EnumSet::default(),
+            config_overrides: EnumMap::default(),
+        }
+    }
+    /// Create a context for the package that can be sent to another thread,
+    /// to run the stages that don't need config items (for example, download and install)
+    pub fn fork(&self, pkg: PkgId) -> Self {
+        Self {
+            pkg,
+            stage: cu::Atomic::new_u8(self.stage.get().into()),
+            items: RefCell::new(ItemMgr::default()),
+            forked: true,
+            bar: self.bar.clone(),
+            installed: self.installed,
+            config_overrides: self.config_overrides.clone(),
         }
     }
     pub fn pkg_name(&self) -> &'static str {
@@ -36,6 +56,9 @@ impl Context {
         if self.stage.get() != Stage::Configure {
             cu::bail!("config items may only be modified during the configure stage");
         }
+        if self.forked {
+            cu::bail!("unexpected: config items are not available in a forked context");
+        }
         cu::check!(
             self.items.try_borrow_mut(),
             "unexpected: failed to borrow items_mut"
@@ -64,12 +87,20 @@ impl Context {
         hmgr::paths::temp_dir(self.pkg_name())
     }
     pub fn load_config<T: for<'de> Deserialize<'de>>(&self, def: ConfigDef<T>) -> cu::Result<T> {
+        let result = match &self.config_overrides[self.pkg] {
+            None => def.load(self.config_file()),
+            Some(overrides) => def.load_with_overrides(self.config_file(), overrides),
+        };
         cu::check!(
-            def.load(self.config_file()),
+            result,
             "failed to load config file for package '{}'",
             self.pkg
         )
     }
+    /// Set values to apply on top of the config file of the package when loading its config
+    pub fn set_config_overrides(&mut self, pkg: PkgId, overrides: Option<toml::Table>) {
+        self.config_overrides[pkg] = overrides;
+    }
     pub fn config_file(&self) -> PathBuf {
         hmgr::paths::config_file(self.pkg_name())
     }
@@ -116,15 +147,22 @@ impl Context {
 }
 
 /// Stages when working with the package
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
 #[repr(u8)]
 pub enum Stage {
+    #[display("verify")]
     Verify = 0,
+    #[display("backup")]
     Backup = 1,
+    #[display("download")]
     Download = 2,
+    #[display("install")]
     Install = 3,
+    #[display("configure")]
     Configure = 4,
+    #[display("clean")]
     Clean = 5,
+    #[display("uninstall")]
     Uninstall = 6,
 }
 impl From<Stage> for u8 {
@@ -7,6 +7,9 @@ macro_rules! check_in_path {
             Ok(p) => p,
             Err(_) => {
                 cu::error!("verify: not found in PATH: '{}'", $l);
+                $crate::VerifyReport::record_reason($crate::VerifyReason::BinaryMissing(
+                    $l.to_string(),
+                ));
                 return Ok(Verified::NotInstalled);
             }
         }
@@ -24,10 +27,15 @@ macro_rules! check_in_shaft {
             Err(e) => {
                 cu::error!("verify: not found in PATH: '{}'", $bin);
                 cu::debug!("check_in_shaft failed: {e:?}");
+                $crate::VerifyReport::record_reason($crate::VerifyReason::BinaryMissing($bin.to_string()));
                 return Ok(Verified::NotInstalled);
             }
             Ok(path) => {
                 if path != hmgr::paths::binary(bin_name!($bin)) {
+                    $crate::VerifyReport::record_reason($crate::VerifyReason::BinaryOutsideShaft {
+                        bin: $bin.to_string(),
+                        path: path.clone(),
+                    });
                     cu::bail!(
                         "found existing '{}' installed outside of shaft, please uninstall it first (at '{}'), or ensure the shaft bin has higher priority in PATH",
                         $bin,
@@ -43,10 +51,15 @@ macro_rules! check_in_shaft {
             Err(e) => {
                 cu::error!("verify: not found in PATH: '{}'", $bin);
                 cu::debug!("check_in_shaft failed: {e:?}");
+                $crate::VerifyReport::record_reason($crate::VerifyReason::BinaryMissing($bin.to_string()));
                 return Ok(Verified::NotInstalled);
             }
             Ok(path) => {
                 if path != hmgr::paths::binary(bin_name!($bin)) {
+                    $crate::VerifyReport::record_reason($crate::VerifyReason::BinaryOutsideShaft {
+                        bin: $bin.to_string(),
+                        path: path.clone(),
+                    });
                     cu::bail!(
                         "found existing '{}' installed outside of shaft, please uninstall it first (at '{}'), or ensure the shaft bin has higher priority in PATH; alternatively, use the {} package",
                         $bin,
@@ -70,13 +83,19 @@ pub(crate) use check_in_shaft;
 macro_rules! check_cargo {
     ($bin:literal) => {{ check_cargo!($bin in crate $bin) }};
     ($bin:literal in crate $l:literal) => {{
+        $crate::VerifyReport::record_external($crate::ExternalPackage::Cargo($l.to_string()));
         if cu::which($bin).is_err() {
-        cu::error!("verify: not found in PATH: '{}'", $bin);
+            cu::error!("verify: not found in PATH: '{}'", $bin);
             cu::debug!("check_cargo failed: binary not found: {} (crate {})", $bin, $l);
+            $crate::VerifyReport::record_reason($crate::VerifyReason::BinaryMissing($bin.to_string()));
             return Ok(Verified::NotInstalled);
         }
         match epkg::cargo::installed_info($l)? {
             None => {
+                $crate::VerifyReport::record_reason($crate::VerifyReason::NotCargoInstalled {
+                    bin: $bin.to_string(),
+                    krate: $l.to_string(),
+                });
                 cu::bail!(
                     "current '{}' is not installed with cargo; please uninstall it first, so we can install the '{}' crate",
                     $bin, $l
@@ -91,15 +110,19 @@ pub(crate) use check_cargo;
 /// Check pacman install metadata for a pacman package
 #[cfg(target_os = "linux")]
 macro_rules! check_pacman {
-    ($l:literal) => {
+    ($l:literal) => {{
+        $crate::VerifyReport::record_external($crate::ExternalPackage::Pacman($l.to_string()));
         match epkg::pacman::installed_version($l)? {
             None => {
                 cu::error!("verify: pacman package not installed: '{}'", $l);
+                $crate::VerifyReport::record_reason($crate::VerifyReason::SystemPackageMissing(
+                    $l.to_string(),
+                ));
                 return Ok(Verified::NotInstalled);
             }
             Some(x) => x,
         }
-    };
+    }};
 }
 #[cfg(target_os = "linux")]
 pub(crate) use check_pacman;
@@ -109,16 +132,28 @@ macro_rules! check_outdated {
     ($actual:expr, metadata [ $($package:ident)::* ]:: $($expected:tt)*) => {{
         let a = $actual;
         let e = metadata::$($package)::*::$($expected)*;
+        $crate::VerifyReport::record_version(&a, &e);
         if Version(a).lt(e) {
             cu::error!("verify: {} {} is outdated, new version: {}", stringify!($($package).*), a, e);
+            $crate::VerifyReport::record_reason($crate::VerifyReason::Outdated {
+                name: stringify!($($package).*).to_string(),
+                actual: a.to_string(),
+                expected: e.to_string(),
+            });
             return Ok(Verified::NotUpToDate);
         }
     }};
     ($actual:expr, $expected:expr) => {{
         let a = $actual;
         let e = $expected;
+        $crate::VerifyReport::record_version(&a, &e);
         if Version(a).lt(e) {
             cu::error!("verify: {} {} is outdated, new version: {}", stringify!($expected), a, e);
+            $crate::VerifyReport::record_reason($crate::VerifyReason::Outdated {
+                name: stringify!($expected).to_string(),
+                actual: a.to_string(),
+                expected: e.to_string(),
+            });
             return Ok(Verified::NotUpToDate);
         }
     }};
@@ -144,6 +179,10 @@ macro_rules! check_version_cache {
         match cache.is_uptodate()? {
             None => {
                 cu::error!("verify: new config: {} = {}", cache.id(), cache.version());
+                $crate::VerifyReport::record_reason($crate::VerifyReason::VersionCacheNew {
+                    id: cache.id().to_string(),
+                    version: cache.version().to_string(),
+                });
                 return Ok(Verified::NotInstalled);
             }
             Some(false) => {
@@ -152,6 +191,10 @@ macro_rules! check_version_cache {
                     cache.id(),
                     cache.version()
                 );
+                $crate::VerifyReport::record_reason($crate::VerifyReason::VersionCacheBumped {
+                    id: cache.id().to_string(),
+                    version: cache.version().to_string(),
+                });
                 return Ok(Verified::NeedsConfig);
             }
             _ => {}
@@ -4,7 +4,7 @@ use corelib::opfs;
 use cu::pre::*;
 use enumset::EnumSet;
 
-use crate::{BinId, Context, PkgId, Verified};
+use crate::{BinId, Context, PkgId, Verified, VerifyReason, VerifyReport};
 
 /// Metadata for a package
 pub struct Package {
@@ -114,6 +114,25 @@ impl Package {
         )
     }
 
+    /// Verify the package, and report the reason if it's not up-to-date
+    /// and the versions that were checked.
+    ///
+    /// Errors from verifying are put into the report instead of being returned
+    pub fn verify_report(&self, ctx: &Context) -> VerifyReport {
+        VerifyReport::begin();
+        let result = self.verify(ctx);
+        let mut report = VerifyReport::end();
+        match result {
+            Ok(verified) => report.verified = Some(verified),
+            Err(e) => {
+                if report.reason.is_none() {
+                    report.reason = Some(VerifyReason::Error(format!("{e:#}")));
+                }
+            }
+        }
+        report
+    }
+
     #[inline(always)]
     #[cu::context("failed to pre-uninstall package '{}'", ctx.pkg)]
     pub fn pre_uninstall(&self, ctx: &Context) -> cu::Result<()> {
@@ -184,13 +203,13 @@ impl Package {
         (self.backup_fn)(ctx)
     }
 
+    /// Backup the package, and restore it when the guard is dropped unless cleared.
+    ///
+    /// The guard keeps a fork of the context, so it can be sent to other threads
     #[inline(always)]
-    pub fn backup_guard<'a, 'b>(
-        &'a self,
-        ctx: &'b Context,
-    ) -> cu::Result<PackageRestoreGuard<'a, 'b>> {
+    pub fn backup_guard(&self, ctx: &Context) -> cu::Result<PackageRestoreGuard<'_>> {
         self.backup(ctx)?;
-        Ok(PackageRestoreGuard::new(self, ctx))
+        Ok(PackageRestoreGuard::new(self, ctx.fork(ctx.pkg)))
     }
 
     #[inline(always)]
@@ -200,13 +219,13 @@ impl Package {
     }
 }
 
-pub struct PackageRestoreGuard<'a, 'b> {
+pub struct PackageRestoreGuard<'a> {
     package: &'a Package,
-    context: &'b Context,
+    context: Context,
     needs_restore: bool,
 }
-impl<'a, 'b> PackageRestoreGuard<'a, 'b> {
-    pub fn new(package: &'a Package, context: &'b Context) -> Self {
+impl<'a> PackageRestoreGuard<'a> {
+    pub fn new(package: &'a Package, context: Context) -> Self {
         Self {
             package,
             context,
@@ -219,10 +238,10 @@ impl<'a, 'b> PackageRestoreGuard<'a, 'b> {
     }
 }
 
-impl<'a, 'b> Drop for PackageRestoreGuard<'a, 'b> {
+impl Drop for PackageRestoreGuard<'_> {
     fn drop(&mut self) {
         if self.needs_restore {
-            if let Err(e) = self.package.restore(self.context) {
+            if let Err(e) = self.package.restore(&self.context) {
                 cu::error!("failed to restore package '{}': {:?}", self.context.pkg, e);
             }
         }
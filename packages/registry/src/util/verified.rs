@@ -1,3 +1,9 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+use corelib::epkg;
+use cu::pre::*;
+
 /// Package verification status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Verified {
@@ -19,3 +25,210 @@ impl Verified {
         }
     }
 }
+
+/// Reason a package did not verify as up-to-date
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum VerifyReason {
+    /// The binary is not found in PATH
+    #[display("'{_0}' not found in PATH")]
+    BinaryMissing(String),
+    /// The binary is found in PATH, but not at the location managed by shaft
+    #[display("'{bin}' found outside of shaft at '{}'", path.display())]
+    BinaryOutsideShaft { bin: String, path: PathBuf },
+    /// The binary is found, but not installed with cargo
+    #[display("'{bin}' not installed with cargo (crate '{krate}')")]
+    NotCargoInstalled { bin: String, krate: String },
+    /// The system package is not installed
+    #[display("system package '{_0}' not installed")]
+    SystemPackageMissing(String),
+    /// The installed version is older than expected
+    #[display("{name} {actual} < {expected}")]
+    Outdated {
+        name: String,
+        actual: String,
+        expected: String,
+    },
+    /// The version cache is not recorded yet
+    #[display("new config {id} = {version}")]
+    VersionCacheNew { id: String, version: String },
+    /// The version cache changed since the package was configured
+    #[display("config {id} bumped to {version}")]
+    VersionCacheBumped { id: String, version: String },
+    /// Verification failed with an error
+    #[display("{_0}")]
+    Error(String),
+}
+
+/// Package from another package manager that a package is installed with
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum ExternalPackage {
+    /// Crate installed with cargo
+    #[display("cargo crate '{_0}'")]
+    Cargo(String),
+    /// System package installed with pacman
+    #[display("pacman package '{_0}'")]
+    Pacman(String),
+    /// Package installed with winget
+    #[display("winget package '{_0}'")]
+    Winget(String),
+}
+impl ExternalPackage {
+    /// Check if the package can be installed without network access.
+    ///
+    /// For cargo, only the crate itself is checked, not its dependencies,
+    /// so installing it offline can still fail
+    pub fn is_cached(&self) -> bool {
+        match self {
+            Self::Cargo(x) => epkg::cargo::is_cached(x),
+            #[cfg(target_os = "linux")]
+            Self::Pacman(x) => epkg::pacman::is_cached(x),
+            #[cfg(not(target_os = "linux"))]
+            Self::Pacman(_) => false,
+            // winget always downloads the installer
+            Self::Winget(_) => false,
+        }
+    }
+}
+
+/// Structured result of verifying a package
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Verification status, `None` if verification failed with an error
+    pub verified: Option<Verified>,
+    /// The first reason the package is not up-to-date
+    pub reason: Option<VerifyReason>,
+    /// The installed version, if checked
+    pub version: Option<String>,
+    /// The expected version, if checked
+    pub expected_version: Option<String>,
+    /// Packages from other package managers that are checked
+    pub external: Vec<ExternalPackage>,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<VerifyReport>> = const { RefCell::new(None) };
+}
+
+impl VerifyReport {
+    /// Start recording reasons and versions from the verify macros on the current thread
+    pub(crate) fn begin() {
+        RECORDER.with_borrow_mut(|x| *x = Some(Self::default()));
+    }
+    /// Stop recording and take the report
+    pub(crate) fn end() -> Self {
+        RECORDER.with_borrow_mut(|x| x.take()).unwrap_or_default()
+    }
+    /// Record the reason if recording, only the first reason is kept
+    pub(crate) fn record_reason(reason: VerifyReason) {
+        RECORDER.with_borrow_mut(|x| {
+            let Some(report) = x else {
+                return;
+            };
+            if report.reason.is_none() {
+                report.reason = Some(reason);
+            }
+        });
+    }
+    /// Record a package from another package manager that is checked, if recording
+    pub(crate) fn record_external(external: ExternalPackage) {
+        RECORDER.with_borrow_mut(|x| {
+            if let Some(report) = x {
+                report.external.push(external);
+            }
+        });
+    }
+    /// Record the version checked if recording, only the first version is kept
+    pub(crate) fn record_version(actual: impl std::fmt::Display, expected: impl std::fmt::Display) {
+        RECORDER.with_borrow_mut(|x| {
+            let Some(report) = x else {
+                return;
+            };
+            if report.version.is_none() {
+                report.version = Some(actual.to_string());
+                report.expected_version = Some(expected.to_string());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pre::*;
+
+    const EXPECTED_VERSION: &str = "1.2.0";
+
+    fn verify_version(actual: &str) -> cu::Result<Verified> {
+        check_outdated!(actual, EXPECTED_VERSION);
+        Ok(Verified::UpToDate)
+    }
+
+    fn verify_cargo() -> cu::Result<Verified> {
+        let _info = check_cargo!("shaft-test-missing-binary" in crate "shaft-test-missing");
+        Ok(Verified::UpToDate)
+    }
+
+    #[test]
+    fn test_record_and_take() {
+        VerifyReport::record_reason(VerifyReason::Error("ignored".to_string()));
+        assert!(VerifyReport::end().reason.is_none());
+
+        VerifyReport::begin();
+        VerifyReport::record_reason(VerifyReason::BinaryMissing("a".to_string()));
+        VerifyReport::record_reason(VerifyReason::BinaryMissing("b".to_string()));
+        VerifyReport::record_version("1.0", "2.0");
+        VerifyReport::record_version("3.0", "4.0");
+        let report = VerifyReport::end();
+        assert_eq!(
+            report.reason,
+            Some(VerifyReason::BinaryMissing("a".to_string()))
+        );
+        assert_eq!(report.version.as_deref(), Some("1.0"));
+        assert_eq!(report.expected_version.as_deref(), Some("2.0"));
+
+        // taken by the first end
+        let report = VerifyReport::end();
+        assert!(report.reason.is_none());
+        assert!(report.version.is_none());
+    }
+
+    #[test]
+    fn test_check_outdated_reason() {
+        VerifyReport::begin();
+        assert_eq!(verify_version("1.1.9").unwrap(), Verified::NotUpToDate);
+        let report = VerifyReport::end();
+        assert_eq!(
+            report.reason,
+            Some(VerifyReason::Outdated {
+                name: "EXPECTED_VERSION".to_string(),
+                actual: "1.1.9".to_string(),
+                expected: "1.2.0".to_string(),
+            })
+        );
+        assert_eq!(report.version.as_deref(), Some("1.1.9"));
+        assert_eq!(report.expected_version.as_deref(), Some("1.2.0"));
+
+        VerifyReport::begin();
+        assert_eq!(verify_version("1.2.0").unwrap(), Verified::UpToDate);
+        let report = VerifyReport::end();
+        assert!(report.reason.is_none());
+        assert_eq!(report.version.as_deref(), Some("1.2.0"));
+    }
+
+    #[test]
+    fn test_check_cargo_reason() {
+        VerifyReport::begin();
+        assert_eq!(verify_cargo().unwrap(), Verified::NotInstalled);
+        let report = VerifyReport::end();
+        assert_eq!(
+            report.reason,
+            Some(VerifyReason::BinaryMissing(
+                "shaft-test-missing-binary".to_string()
+            ))
+        );
+        assert_eq!(
+            report.external,
+            vec![ExternalPackage::Cargo("shaft-test-missing".to_string())]
+        );
+    }
+}